@@ -0,0 +1,50 @@
+use bevy::{
+    app::{AppExit, ScheduleRunnerPlugin},
+    log::LogPlugin,
+    prelude::*,
+};
+use core::time::Duration;
+use fos_server::{
+    dedicated::{DedicatedServerConfig, DedicatedServerPlugin, USAGE},
+    server::console::StdinConsolePlugin,
+    storage, FOSServerPlugin,
+};
+
+/// Simulation rate of the headless server.
+const TICKS_PER_SECOND: f64 = 60.0;
+
+fn main() -> AppExit {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{USAGE}");
+        return AppExit::Success;
+    }
+    let config = match DedicatedServerConfig::from_args(args) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{err:#}\n\n{USAGE}");
+            return AppExit::error();
+        }
+    };
+    // Everything persisted (identity, ban and allow lists) goes through `storage::data_dir`.
+    if let Some(data_dir) = &config.data_dir {
+        // Nothing read the data dir yet, so this is the first and only call.
+        let _ = storage::set_data_dir(data_dir.clone());
+    }
+
+    App::new()
+        .add_plugins((
+            MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(
+                1.0 / TICKS_PER_SECOND,
+            ))),
+            LogPlugin::default(),
+            // The session states and the chat still read keyboard input, it just never arrives.
+            bevy::input::InputPlugin,
+            bevy::state::app::StatesPlugin,
+            FOSServerPlugin,
+            DedicatedServerPlugin,
+            StdinConsolePlugin,
+        ))
+        .insert_resource(config)
+        .run()
+}
@@ -3,10 +3,18 @@ use bevy::{ecs::system::SystemParam, prelude::*};
 use bevy_egui::{egui, EguiContexts, EguiPlugin, EguiPrimaryContextPass};
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
 use fos_server::{
-    client::{ClientTarget, DiscoveredServers, SetClientTarget},
+    client::{
+        known_hosts::{FingerprintMismatch, TrustNewServerKey},
+        CancelConnect, ClientTarget, ConnectAttempt, DiscoveredServer, DiscoveredServers,
+        SetClientTarget, TargetStatus,
+    },
+    network_stats::NetworkStats,
+    player::{PlayerIdentity, PlayerProfile, SetPlayerName},
+    server::console::{CommandSource, ConsoleLog, RunAdminCommand},
     status_management::*,
     *,
 };
+use std::net::SocketAddr;
 
 fn main() -> AppExit {
     App::new()
@@ -16,7 +24,13 @@ fn main() -> AppExit {
             WorldInspectorPlugin::new(),
             FOSServerPlugin,
         ))
+        .init_resource::<NetworkStatsOverlay>()
         .add_systems(Startup, setup_camera_system)
+        .add_systems(Update, toggle_network_stats_overlay)
+        .add_systems(
+            EguiPrimaryContextPass,
+            ui_network_stats_overlay.run_if(|overlay: Res<NetworkStatsOverlay>| overlay.0),
+        )
         .add_systems(
             EguiPrimaryContextPass,
             ui_menu_system.run_if(in_state(AppScope::Menu)),
@@ -27,6 +41,12 @@ fn main() -> AppExit {
                 .run_if(in_state(AppScope::InGame))
                 .run_if(in_state(SessionType::Singleplayer)),
         )
+        .add_systems(
+            EguiPrimaryContextPass,
+            ui_admin_console
+                .run_if(in_state(AppScope::InGame))
+                .run_if(in_state(SessionType::Singleplayer)),
+        )
         .add_systems(
             EguiPrimaryContextPass,
             ui_client_system
@@ -51,6 +71,69 @@ fn setup_camera_system(mut commands: Commands) {
     commands.spawn(Camera2d);
 }
 
+/// Whether the connection statistics are shown, toggled with F3.
+#[derive(Resource, Default)]
+struct NetworkStatsOverlay(bool);
+
+fn toggle_network_stats_overlay(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut overlay: ResMut<NetworkStatsOverlay>,
+) {
+    if keys.just_pressed(KeyCode::F3) {
+        overlay.0 = !overlay.0;
+    }
+}
+
+fn ui_network_stats_overlay(
+    mut egui: EguiContexts,
+    sessions: Query<(
+        Entity,
+        &NetworkStats,
+        Option<&PlayerIdentity>,
+        Option<&Name>,
+    )>,
+) -> Result<(), bevy::prelude::BevyError> {
+    egui::Window::new("Network")
+        .resizable(false)
+        .anchor(egui::Align2::LEFT_BOTTOM, egui::Vec2::new(10.0, -10.0))
+        .show(egui.ctx_mut()?, |ui| {
+            if sessions.is_empty() {
+                ui.label("No connections");
+                return;
+            }
+            egui::Grid::new("network_stats")
+                .striped(true)
+                .show(ui, |ui| {
+                    for header in ["Session", "RTT", "Loss", "In", "Out", "Msgs in/out"] {
+                        ui.strong(header);
+                    }
+                    ui.end_row();
+                    for (entity, stats, identity, name) in &sessions {
+                        let label = identity
+                            .map(|identity| identity.name.clone())
+                            .or_else(|| name.map(|name| name.to_string()))
+                            .unwrap_or_else(|| entity.to_string());
+                        ui.label(label);
+                        let rtt = format!("{} ms", stats.rtt.as_millis());
+                        if stats.is_degraded() {
+                            ui.colored_label(egui::Color32::YELLOW, rtt);
+                        } else {
+                            ui.label(rtt);
+                        }
+                        ui.label(format!("{:.1}%", stats.packet_loss * 100.0));
+                        ui.label(format!("{:.1} kB/s", stats.bytes_recv_per_sec / 1000.0));
+                        ui.label(format!("{:.1} kB/s", stats.bytes_sent_per_sec / 1000.0));
+                        ui.label(format!(
+                            "{:.0}/{:.0}",
+                            stats.msgs_recv_per_sec, stats.msgs_sent_per_sec
+                        ));
+                        ui.end_row();
+                    }
+                });
+        });
+    Ok(())
+}
+
 #[derive(SystemParam)]
 struct MenuUiParams<'w, 's> {
     commands: Commands<'w, 's>,
@@ -61,6 +144,11 @@ struct MenuUiParams<'w, 's> {
     multiplayer_menu_state: Option<Res<'w, State<MultiplayerSetup>>>,
     discovered_servers: Option<Res<'w, DiscoveredServers>>,
     client_target: Option<ResMut<'w, ClientTarget>>,
+    fingerprint_mismatch: Option<Res<'w, FingerprintMismatch>>,
+    connect_attempt: Option<Res<'w, ConnectAttempt>>,
+    player_profile: Res<'w, PlayerProfile>,
+    // Edited in the settings menu, only applied on "Save".
+    player_name_input: Local<'s, Option<String>>,
 }
 
 struct MenuActions<'w, 's> {
@@ -102,12 +190,52 @@ fn ui_singleplayer_system(
     Ok(())
 }
 
+fn ui_admin_console(
+    mut commands: Commands,
+    mut egui: EguiContexts,
+    log: Res<ConsoleLog>,
+    mut input: Local<String>,
+) -> Result<(), bevy::prelude::BevyError> {
+    egui::Window::new("Admin Console")
+        .default_open(false)
+        .anchor(egui::Align2::RIGHT_TOP, egui::Vec2::new(-10.0, 10.0))
+        .show(egui.ctx_mut()?, |ui| {
+            egui::ScrollArea::vertical()
+                .stick_to_bottom(true)
+                .max_height(200.0)
+                .show(ui, |ui| {
+                    ui.set_min_width(ui.available_width());
+                    for line in &log.lines {
+                        ui.monospace(line);
+                    }
+                });
+            ui.separator();
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut *input)
+                    .hint_text("help")
+                    .desired_width(f32::INFINITY),
+            );
+            if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                commands.trigger(RunAdminCommand {
+                    line: std::mem::take(&mut *input),
+                    source: CommandSource::Console,
+                });
+                response.request_focus();
+            }
+        });
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
 fn ui_client_system(
     mut _commands: Commands,
     mut egui: EguiContexts,
     app_state: Res<State<AppScope>>,
     game_mode_state: Res<State<SessionType>>,
     client_state: Res<State<ClientStatus>>,
+    reconnect: Option<Res<fos_server::client::ReconnectState>>,
+    sync_progress: Option<Res<fos_server::client::SyncProgress>>,
+    shutdown_countdown: Option<Res<fos_server::client::ServerShutdownCountdown>>,
 ) -> Result<(), bevy::prelude::BevyError> {
     egui::Window::new("APP Game - Client").show(egui.ctx_mut()?, |ui| {
         ui.vertical_centered_justified(|ui| {
@@ -117,12 +245,39 @@ fn ui_client_system(
                     "States: \n AppScope: {:?}\nGameMode: {:?}\nClientState: {:?}\n",
                     app_state, game_mode_state, client_state
                 ));
+                if let Some(reconnect) = &reconnect {
+                    ui.horizontal(|ui| {
+                        ui.label(format!(
+                            "Reconnecting (attempt {}/{})",
+                            reconnect.attempt, reconnect.max_attempts
+                        ));
+                        ui.add(egui::Spinner::new());
+                    });
+                }
+                if let Some(progress) = &sync_progress {
+                    ui.horizontal(|ui| {
+                        ui.label(match progress.expected {
+                            Some(expected) => format!(
+                                "Syncing world ({}/{expected} entities)",
+                                progress.received
+                            ),
+                            None => {
+                                format!("Syncing world ({} entities received)", progress.received)
+                            }
+                        });
+                        ui.add(egui::Spinner::new());
+                    });
+                }
+                if let Some(countdown) = &shutdown_countdown {
+                    ui.colored_label(egui::Color32::YELLOW, countdown.describe());
+                }
             }
         });
     });
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 fn ui_game_menu(
     mut commands: Commands,
     mut egui: EguiContexts,
@@ -130,6 +285,8 @@ fn ui_game_menu(
     game_mode_state: Res<State<SessionType>>,
     in_game_mode_state: Res<State<SessionStatus>>,
     server_visibility: Option<Res<State<ServerVisibility>>>,
+    server_ports: Option<Res<fos_server::server::ActiveServerPorts>>,
+    server_identity: Option<Res<fos_server::server::ServerIdentity>>,
 ) -> Result<(), bevy::prelude::BevyError> {
     egui::Window::new("APP Game Menu").show(egui.ctx_mut()?, |ui| {
         ui.vertical_centered_justified(|ui| {
@@ -150,7 +307,28 @@ fn ui_game_menu(
                                 }
                                 ServerVisibility::Public => {
                                     if let Some(ip) = fos_server::server::helpers::get_local_ip() {
-                                        ui.label(format!("Server IP: {}", ip));
+                                        match &server_ports {
+                                            Some(ports) => ui.label(format!(
+                                                "Server IP: {}:{}",
+                                                ip, ports.game_port
+                                            )),
+                                            None => ui.label(format!("Server IP: {}", ip)),
+                                        };
+                                        if let (Some(ports), Some(identity)) =
+                                            (&server_ports, &server_identity)
+                                        {
+                                            let link = protocol::join_link::encode(
+                                                SocketAddr::new(ip, ports.game_port),
+                                                &identity.spki_fingerprint,
+                                            );
+                                            ui.label("Join link:");
+                                            ui.horizontal(|ui| {
+                                                ui.monospace(&link);
+                                                if ui.button("Copy").clicked() {
+                                                    ui.ctx().copy_text(link.clone());
+                                                }
+                                            });
+                                        }
                                     }
                                     ui.button("Close to LAN").clicked().then(|| {
                                         commands.trigger(SetServerVisibility {
@@ -196,6 +374,10 @@ fn ui_menu_system(mut params: MenuUiParams) -> Result<(), BevyError> {
     let multi = params.multiplayer_menu_state.as_deref();
     let discovered = params.discovered_servers.as_deref();
     let client_target = params.client_target.as_deref_mut();
+    let fingerprint_mismatch = params.fingerprint_mismatch.as_deref();
+    let connect_attempt = params.connect_attempt.as_deref();
+    let player_profile = &params.player_profile;
+    let player_name_input = &mut *params.player_name_input;
 
     // 3. Build mutable "Action" bundle for Commands + Exit
     let mut actions = MenuActions {
@@ -211,11 +393,19 @@ fn ui_menu_system(mut params: MenuUiParams) -> Result<(), BevyError> {
             match menu_state.get() {
                 MainMenuContext::Main => render_menu_main(ui, &mut actions),
                 MainMenuContext::Singleplayer => render_singleplayer_menu(ui, &mut actions, single),
-                MainMenuContext::Multiplayer => {
-                    render_multiplayer_menu(ui, &mut actions, multi, discovered, client_target)
-                }
+                MainMenuContext::Multiplayer => render_multiplayer_menu(
+                    ui,
+                    &mut actions,
+                    multi,
+                    discovered,
+                    client_target,
+                    fingerprint_mismatch,
+                    connect_attempt,
+                ),
                 MainMenuContext::Wiki => render_menu_wiki(ui, &mut actions),
-                MainMenuContext::Settings => render_menu_settings(ui, &mut actions),
+                MainMenuContext::Settings => {
+                    render_menu_settings(ui, &mut actions, player_profile, player_name_input)
+                }
             }
         });
     });
@@ -313,6 +503,8 @@ fn render_multiplayer_menu(
     state: Option<&State<MultiplayerSetup>>,
     discovered_servers: Option<&DiscoveredServers>,
     client_target: Option<&mut ClientTarget>,
+    fingerprint_mismatch: Option<&FingerprintMismatch>,
+    connect_attempt: Option<&ConnectAttempt>,
 ) {
     ui.vertical_centered_justified(|ui| {
         let Some(multi) = state else {
@@ -330,7 +522,14 @@ fn render_multiplayer_menu(
                 render_multiplayer_host_saved(ui, actions);
             }
             MultiplayerSetup::JoinGame => {
-                render_multiplayer_join_game(ui, actions, discovered_servers, client_target);
+                render_multiplayer_join_game(
+                    ui,
+                    actions,
+                    discovered_servers,
+                    client_target,
+                    fingerprint_mismatch,
+                    connect_attempt,
+                );
             }
         }
     });
@@ -385,7 +584,39 @@ fn render_multiplayer_join_game(
     actions: &mut MenuActions,
     discovered_servers: Option<&DiscoveredServers>,
     client_target: Option<&mut ClientTarget>,
+    fingerprint_mismatch: Option<&FingerprintMismatch>,
+    connect_attempt: Option<&ConnectAttempt>,
 ) {
+    if let Some(attempt) = connect_attempt {
+        ui.horizontal(|ui| {
+            ui.label(format!(
+                "Connecting to {}... {}s",
+                attempt.input,
+                attempt.remaining().as_secs_f32().ceil()
+            ));
+            ui.add(egui::Spinner::new());
+        });
+        if ui.button("Cancel").clicked() {
+            actions.commands.trigger(CancelConnect);
+        }
+        return;
+    }
+
+    if let Some(mismatch) = fingerprint_mismatch {
+        ui.colored_label(egui::Color32::RED, mismatch.describe());
+        ui.label(format!("Known key: {}", mismatch.expected));
+        ui.label(format!("New key:   {}", mismatch.presented));
+        ui.horizontal(|ui| {
+            if ui.button("Trust new key").clicked() {
+                actions.commands.trigger(TrustNewServerKey);
+            }
+            if ui.button("Keep old key").clicked() {
+                actions.commands.remove_resource::<FingerprintMismatch>();
+            }
+        });
+        ui.separator();
+    }
+
     ui.heading("Local Servers");
 
     ui.horizontal(|ui| {
@@ -399,10 +630,14 @@ fn render_multiplayer_join_game(
         if !servers.is_empty() {
             ui.separator();
             for server in servers {
-                if ui.selectable_label(false, server.to_string()).clicked() {
+                if ui
+                    .selectable_label(false, discovered_server_label(server))
+                    .clicked()
+                {
                     // ✅ input instead of target
                     actions.commands.queue(SetClientTarget {
-                        input: server.clone(),
+                        input: server.address.clone(),
+                        key_fingerprint: server.info.key_fingerprint.clone(),
                     });
                 }
             }
@@ -415,8 +650,10 @@ fn render_multiplayer_join_game(
     let mut is_client_target_valid = false;
     if let Some(target) = client_target {
         ui.horizontal(|ui| {
-            let response =
-                ui.add(egui::TextEdit::singleline(&mut target.input).hint_text("127.0.0.1:8080"));
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut target.input)
+                    .hint_text("myhost.lan or 192.168.1.10:25571"),
+            );
 
             if response.changed() {
                 let val = target.input.clone();
@@ -424,20 +661,34 @@ fn render_multiplayer_join_game(
             }
 
             // Display status
-            ui.label(match target.is_valid {
-                true => "Valid",
-                false => {
-                    if target.input.trim().is_empty() {
-                        ""
-                    } else {
-                        "Invalid"
-                    }
+            match &target.status {
+                TargetStatus::Empty => {}
+                TargetStatus::Resolving => {
+                    ui.label("Resolving...");
+                    ui.add(egui::Spinner::new());
                 }
-            });
+                TargetStatus::Resolved => {
+                    ui.label("Valid");
+                }
+                TargetStatus::Invalid(reason) => {
+                    ui.colored_label(egui::Color32::RED, format!("Invalid: {reason}"));
+                }
+                TargetStatus::Unresolved(reason) => {
+                    ui.colored_label(egui::Color32::YELLOW, reason);
+                }
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Password:");
+            ui.add(
+                egui::TextEdit::singleline(&mut target.password)
+                    .password(true)
+                    .hint_text("only if the server asks for one"),
+            );
         });
         ui.label(format!(
-            "Client Target:\nInput:{}\nIP-Address:{:?}\nPort:{}\nIs valid:{}",
-            target.input, target.ip, target.port, target.is_valid
+            "Client Target:\nInput:{}\nHost:{}\nIP-Address:{:?}\nPort:{}\nIs valid:{}\nKey fingerprint:{}",
+            target.input, target.host, target.ip, target.port, target.is_valid, target.key_fingerprint
         ));
 
         is_client_target_valid = target.is_valid;
@@ -461,6 +712,31 @@ fn render_multiplayer_join_game(
     }
 }
 
+fn discovered_server_label(server: &DiscoveredServer) -> String {
+    let info = &server.info;
+    let mut label = String::new();
+    if info.key_fingerprint.is_some() {
+        label.push_str("🔒 ");
+    }
+    if info.password_required {
+        label.push_str("🔑 ");
+    }
+    label.push_str(server.display_name());
+
+    if info.protocol_version >= 2 {
+        let players = match info.max_players {
+            0 => format!("{}", info.current_players),
+            max_players => format!("{}/{}", info.current_players, max_players),
+        };
+        label.push_str(&format!(
+            " | {} | {} players | v{}",
+            info.world_name, players, info.game_version
+        ));
+    }
+    label.push_str(&format!(" | {} ms", server.round_trip.as_millis()));
+    label
+}
+
 fn render_menu_wiki(ui: &mut egui::Ui, actions: &mut MenuActions) {
     ui.vertical_centered_justified(|ui| {
         if ui.button("Back").clicked() {
@@ -471,9 +747,31 @@ fn render_menu_wiki(ui: &mut egui::Ui, actions: &mut MenuActions) {
     });
 }
 
-fn render_menu_settings(ui: &mut egui::Ui, actions: &mut MenuActions) {
+fn render_menu_settings(
+    ui: &mut egui::Ui,
+    actions: &mut MenuActions,
+    player_profile: &PlayerProfile,
+    player_name_input: &mut Option<String>,
+) {
     ui.vertical_centered_justified(|ui| {
+        let name = player_name_input.get_or_insert_with(|| player_profile.name.clone());
+        ui.horizontal(|ui| {
+            ui.label("Player name:");
+            ui.text_edit_singleline(name);
+        });
+        if ui
+            .add_enabled(*name != player_profile.name, egui::Button::new("Save"))
+            .clicked()
+        {
+            actions
+                .commands
+                .trigger(SetPlayerName { name: name.clone() });
+        }
+
+        ui.separator();
+
         if ui.button("Back").clicked() {
+            *player_name_input = None;
             actions
                 .commands
                 .trigger(MainMenuInteraction::SwitchContext(MainMenuContext::Main));
@@ -1,7 +1,8 @@
+#[cfg(feature = "ui")]
+use bevy_egui::{egui, EguiContexts};
 use {
     crate::protocol::{ClientChat, ServerChat},
     bevy::prelude::*,
-    bevy_egui::{egui, EguiContexts},
 };
 
 pub struct ChatPlugin;
@@ -59,13 +60,14 @@ fn handle_chat_input(
     }
 
     // Send with Enter
-    if keyboard.just_pressed(KeyCode::Enter) && chat_state.has_focus {
-        if !chat_state.input.trim().is_empty() {
-            client_chat_writer.write(ClientChat {
-                text: chat_state.input.clone(),
-            });
-            chat_state.input.clear();
-        }
+    if keyboard.just_pressed(KeyCode::Enter)
+        && chat_state.has_focus
+        && !chat_state.input.trim().is_empty()
+    {
+        client_chat_writer.write(ClientChat {
+            text: chat_state.input.clone(),
+        });
+        chat_state.input.clear();
         // Keep focus or close after send? Usually keep focus in modern games,
         // but often close in simple MMOs. Let's keep it open for now,
         // or user can press Esc to close.
@@ -73,6 +75,7 @@ fn handle_chat_input(
     }
 }
 
+#[cfg(feature = "ui")]
 pub fn render_chat_ui(mut egui: EguiContexts, mut chat_state: ResMut<ChatState>) {
     // Always show chat history (maybe faded?) or only when open?
     // Let's emulate a typical MMO chat: Always visible background (transparent),
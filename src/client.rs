@@ -2,7 +2,14 @@ use {
     crate::{
         local::LocalClient,
         notifications::Notify,
-        server::helpers::{DISCOVERY_PORT, MAGIC},
+        player::PlayerProfile,
+        protocol::{
+            discovery::{
+                self, DiscoveryRequest, DiscoveryResponse, DISCOVERY_PORT, MAX_PACKET_SIZE,
+                PROTOCOL_VERSION, REQUEST_MAGIC_V1,
+            },
+            join_link, ServerShutdown, SyncComplete,
+        },
         status_management::{
             ClientShutdownStep, ClientStatus, MultiplayerSetup, SetClientShutdownStep,
             SetClientStatus,
@@ -14,43 +21,115 @@ use {
     },
     aeronet_io::connection::DisconnectReason,
     aeronet_replicon::client::AeronetRepliconClient,
-    aeronet_webtransport::client::{WebTransportClient, WebTransportClientPlugin},
+    aeronet_webtransport::{
+        cert,
+        client::{WebTransportClient, WebTransportClientPlugin},
+    },
     bevy::{
         prelude::*,
         tasks::{futures::check_ready, AsyncComputeTaskPool, Task},
     },
+    bevy_replicon::prelude::{ClientState, ProtocolHash, Replicated},
     helpers::client_config,
-    std::{net::UdpSocket, time::Duration},
+    known_hosts::{host_key, HostFingerprint, KnownHosts, KnownHostsPlugin, PresentedCertificate},
+    std::{
+        net::{IpAddr, SocketAddr, SocketAddrV6, ToSocketAddrs},
+        time::{Duration, Instant},
+    },
 };
 
+pub mod known_hosts;
+
 pub struct ClientLogicPlugin;
 
 impl Plugin for ClientLogicPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins(WebTransportClientPlugin)
+        app.add_plugins((WebTransportClientPlugin, KnownHostsPlugin))
             .init_resource::<DiscoveredServers>()
             .init_resource::<ClientTarget>()
+            .init_resource::<DiscoveredServerTtl>()
+            .init_resource::<ReconnectPolicy>()
+            .init_resource::<ConnectTimeout>()
+            .init_resource::<SyncTimeout>()
             .insert_resource(DiscoveryTimer(Timer::from_seconds(
                 2.0,
                 TimerMode::Repeating,
             )))
             .add_systems(OnEnter(ClientStatus::Connecting), on_client_connecting)
+            .add_systems(
+                Update,
+                client_connecting.run_if(in_state(ClientStatus::Connecting)),
+            )
+            .add_systems(OnExit(ClientStatus::Connecting), on_client_stop_connecting)
+            .add_systems(
+                OnExit(ClientState::Connected),
+                (despawn_replicated_entities, clear_server_shutdown_countdown),
+            )
+            .add_systems(OnEnter(ClientStatus::Connected), on_client_enter_connected)
+            .add_systems(
+                Update,
+                client_connected.run_if(
+                    in_state(ClientStatus::Connected).and(in_state(ClientState::Connected)),
+                ),
+            )
+            .add_observer(on_cancel_connect)
+            .add_systems(
+                OnEnter(ClientStatus::Reconnecting),
+                on_client_start_reconnecting,
+            )
+            .add_systems(
+                Update,
+                client_reconnecting.run_if(in_state(ClientStatus::Reconnecting)),
+            )
+            .add_systems(
+                OnExit(ClientStatus::Reconnecting),
+                on_client_stop_reconnecting,
+            )
             .add_systems(
                 OnEnter(ClientStatus::Disconnecting),
                 on_client_start_disconnecting,
             )
             .add_systems(
                 Update,
-                client_syncing.run_if(in_state(ClientStatus::Syncing)),
+                (
+                    count_received_entities,
+                    receive_sync_complete.run_if(resource_exists::<SyncProgress>),
+                    client_syncing.run_if(in_state(ClientStatus::Syncing)),
+                )
+                    .chain(),
             )
+            .add_systems(OnExit(ClientStatus::Syncing), on_client_stop_syncing)
             .add_systems(
                 Update,
                 client_disconnecting.run_if(in_state(ClientStatus::Disconnecting)),
             )
             .add_systems(
                 Update,
-                (client_discover_server, client_discover_server_collect)
+                (
+                    receive_server_shutdown.run_if(in_state(ClientStatus::Running)),
+                    tick_server_shutdown_countdown
+                        .run_if(resource_exists::<ServerShutdownCountdown>),
+                )
+                    .chain(),
+            )
+            .add_systems(
+                Update,
+                (
+                    client_discover_server,
+                    client_discover_server_collect,
+                    client_discover_server_expire,
+                )
+                    .chain()
                     .run_if(in_state(MultiplayerSetup::JoinGame)),
+            )
+            .add_systems(OnExit(MultiplayerSetup::JoinGame), clear_discovered_servers)
+            .add_systems(
+                Update,
+                (
+                    client_resolve_target.run_if(resource_changed::<ClientTarget>),
+                    client_resolve_target_collect,
+                )
+                    .chain(),
             );
     }
 }
@@ -61,7 +140,7 @@ fn on_client_disconnected(
     mut commands: Commands,
 ) {
     match state.get() {
-        ClientStatus::Syncing | ClientStatus::Running => {
+        ClientStatus::Connected | ClientStatus::Syncing | ClientStatus::Running => {
             on_client_receive_disconnect(&trigger.reason, &mut commands);
         }
         _ => {}
@@ -74,9 +153,14 @@ pub fn on_client_receive_disconnect(reason: &DisconnectReason, commands: &mut Co
             info!("Server closed connection: {msg}");
             commands.trigger(Notify::info(format!("Server closed connection: {msg}")));
         }
+        // Only network trouble is worth retrying, the server closing on us is deliberate.
         DisconnectReason::ByError(err) => {
-            error!("Connection lost: {err}");
-            commands.trigger(Notify::error(format!("Connection lost: {err}")));
+            warn!("Connection lost: {err}");
+            commands.trigger(Notify::warning(format!(
+                "Connection lost, reconnecting: {err}"
+            )));
+            commands.trigger(SetClientStatus::Transition(ClientStatus::Reconnecting));
+            return;
         }
         DisconnectReason::ByUser(_) => return,
     }
@@ -84,65 +168,348 @@ pub fn on_client_receive_disconnect(reason: &DisconnectReason, commands: &mut Co
     commands.trigger(SetClientStatus::Failed);
 }
 
+/// Removes everything the server replicated to us once the connection is gone.
+///
+/// Replicon only forgets which server entity maps to which of ours, the entities themselves
+/// would stay behind and show up twice after joining again. Hosts are never a replicon
+/// client, so their own replicated entities are left alone.
+fn despawn_replicated_entities(
+    mut commands: Commands,
+    replicated: Query<Entity, With<Replicated>>,
+) {
+    for entity in &replicated {
+        commands.entity(entity).despawn();
+    }
+}
+
+/// A shutdown the server announced, counting down until it disconnects us.
+#[derive(Resource, Debug)]
+pub struct ServerShutdownCountdown {
+    pub reason: String,
+    pub timer: Timer,
+}
+
+impl ServerShutdownCountdown {
+    /// Text shown to players, with the seconds that are still left.
+    pub fn describe(&self) -> String {
+        ServerShutdown {
+            reason: self.reason.clone(),
+            countdown: Some(self.timer.remaining()),
+        }
+        .describe()
+    }
+}
+
+fn receive_server_shutdown(
+    mut shutdown_messages: MessageReader<ServerShutdown>,
+    mut commands: Commands,
+) {
+    for notice in shutdown_messages.read() {
+        commands.trigger(Notify::warning(notice.describe()));
+        if let Some(countdown) = notice.countdown {
+            commands.insert_resource(ServerShutdownCountdown {
+                reason: notice.reason.clone(),
+                timer: Timer::new(countdown, TimerMode::Once),
+            });
+        }
+    }
+}
+
+fn tick_server_shutdown_countdown(time: Res<Time>, mut countdown: ResMut<ServerShutdownCountdown>) {
+    countdown.timer.tick(time.delta());
+}
+
+fn clear_server_shutdown_countdown(mut commands: Commands) {
+    commands.remove_resource::<ServerShutdownCountdown>();
+}
+
 #[derive(Resource, Default)]
 pub struct ClientTarget {
     pub input: String, // "127.0.0.1:8080"
     pub real_address: String,
+    /// Where the session goes once resolved. Unlike `real_address` it keeps the interface
+    /// of a link-local IPv6 address.
+    pub address: Option<SocketAddr>,
+    /// The host as typed in, an IP address or a hostname.
+    pub host: String,
     pub ip: String,
     pub port: u16,
     pub is_valid: bool,
+    pub status: TargetStatus,
+    /// Base64 SPKI fingerprint of the server's key to validate against, empty if unknown.
+    pub key_fingerprint: String,
+    /// Sent along with the session request, empty for servers without a password.
+    pub password: String,
+}
+
+/// How far the typed in address got, only [`TargetStatus::Resolved`] can be joined.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum TargetStatus {
+    #[default]
+    Empty,
+    /// Not an address at all, with the reason.
+    Invalid(String),
+    /// Waiting for the name lookup of a hostname.
+    Resolving,
+    Resolved,
+    /// A well-formed hostname the lookup found nothing for.
+    Unresolved(String),
 }
 
 impl ClientTarget {
     pub fn update_input(&mut self, input: String) {
         self.input = input;
-        let trimmed = self.input.trim();
-
-        if let Some((ip, port)) = helpers::parse_target_live(trimmed) {
-            self.ip = ip;
-            self.port = port;
-
-            // Check if "https://" is already set in the input
-            if trimmed.starts_with("https://") {
-                // It is already there, so we use the trimmed input as the real address
-                self.real_address = trimmed.to_string();
-            } else {
-                // It's not there (or it was "http://"), so we add "https://"
-                // parse_target_live already stripped "http://" if present
-                let host = trimmed.strip_prefix("http://").unwrap_or(trimmed);
-                self.real_address = format!("https://{}", host);
+        // A fingerprint only belongs to the server it was discovered with or the join link it
+        // came in, not to whatever gets typed in.
+        self.key_fingerprint.clear();
+        self.host.clear();
+        self.ip.clear();
+        self.port = 0;
+        self.real_address.clear();
+        self.address = None;
+        self.is_valid = false;
+
+        if self.input.trim().is_empty() {
+            self.status = TargetStatus::Empty;
+            return;
+        }
+        let input = self.input.clone();
+        let address = match join_link::decode(&input) {
+            Some((address, Some(key_fingerprint))) => {
+                if cert::hash_from_b64(&key_fingerprint).is_err() {
+                    self.status = TargetStatus::Invalid(
+                        "The join link's key fingerprint is damaged".to_string(),
+                    );
+                    return;
+                }
+                self.key_fingerprint = key_fingerprint;
+                address
             }
-            self.is_valid = true;
+            Some((address, None)) => address,
+            None => &input,
+        };
+        match helpers::parse_target(address) {
+            Ok(parsed) => {
+                self.port = parsed.port;
+                match parsed.host {
+                    helpers::TargetHost::Ip(ip) => {
+                        let address = match ip {
+                            IpAddr::V6(ip) => {
+                                SocketAddrV6::new(ip, parsed.port, 0, parsed.scope_id).into()
+                            }
+                            IpAddr::V4(_) => SocketAddr::new(ip, parsed.port),
+                        };
+                        self.host = helpers::host_of(address);
+                        self.resolve(address);
+                    }
+                    // Looked up by `client_resolve_target`.
+                    helpers::TargetHost::Name(name) => {
+                        self.host = name;
+                        self.status = TargetStatus::Resolving;
+                    }
+                }
+            }
+            Err(reason) => {
+                self.key_fingerprint.clear();
+                self.status = TargetStatus::Invalid(reason);
+            }
+        }
+    }
+
+    /// Completes the target with the address its host resolved to.
+    pub fn resolve(&mut self, address: SocketAddr) {
+        self.ip = helpers::host_of(address);
+        self.real_address = helpers::session_url(address);
+        self.address = Some(address);
+        self.status = TargetStatus::Resolved;
+        self.is_valid = true;
+    }
+}
+
+/// Name lookup of the [`ClientTarget`] that was typed in as `input`.
+#[derive(Component)]
+pub struct TargetResolution {
+    input: String,
+    task: Task<std::io::Result<Vec<SocketAddr>>>,
+}
+
+pub fn client_resolve_target(
+    mut commands: Commands,
+    target: Res<ClientTarget>,
+    pending: Query<(Entity, &TargetResolution)>,
+) {
+    let mut already_pending = false;
+    for (entity, resolution) in &pending {
+        if resolution.input == target.input && target.status == TargetStatus::Resolving {
+            already_pending = true;
         } else {
-            self.ip.clear();
-            self.port = 0;
-            self.real_address.clear();
-            self.is_valid = false;
+            // The input changed since, dropping the task cancels the stale lookup.
+            commands.entity(entity).despawn();
+        }
+    }
+    if already_pending || target.status != TargetStatus::Resolving {
+        return;
+    }
+
+    let (host, port) = (target.host.clone(), target.port);
+    let task = AsyncComputeTaskPool::get().spawn(async move {
+        (host.as_str(), port)
+            .to_socket_addrs()
+            .map(Iterator::collect)
+    });
+    commands.spawn((
+        Name::new("TargetResolution"),
+        TargetResolution {
+            input: target.input.clone(),
+            task,
+        },
+    ));
+}
+
+pub fn client_resolve_target_collect(
+    mut commands: Commands,
+    mut target: ResMut<ClientTarget>,
+    mut pending: Query<(Entity, &mut TargetResolution)>,
+) {
+    for (entity, mut resolution) in &mut pending {
+        let Some(result) = check_ready(&mut resolution.task) else {
+            continue;
+        };
+        commands.entity(entity).despawn();
+        if resolution.input != target.input || target.status != TargetStatus::Resolving {
+            continue;
+        }
+
+        // The server listens on both families, IPv4 is the one every network routes.
+        let address = result.map(|addresses| {
+            addresses
+                .iter()
+                .find(|address| address.is_ipv4())
+                .or(addresses.first())
+                .copied()
+        });
+        match address {
+            Ok(Some(address)) => target.resolve(address),
+            Ok(None) => {
+                target.status = TargetStatus::Unresolved(format!("{} has no address", target.host));
+            }
+            Err(err) => {
+                target.status =
+                    TargetStatus::Unresolved(format!("Could not resolve {}: {err}", target.host));
+            }
         }
     }
 }
 
 pub struct SetClientTarget {
     pub input: String,
+    pub key_fingerprint: Option<String>,
 }
 
 impl Command for SetClientTarget {
     fn apply(self, world: &mut World) {
         let mut target = ClientTarget::default();
         target.update_input(self.input);
+        // The password is typed in separately and should survive picking another server.
+        if let Some(previous) = world.get_resource::<ClientTarget>() {
+            target.password = previous.password.clone();
+        }
+        if let Some(key_fingerprint) = self.key_fingerprint {
+            target.key_fingerprint = key_fingerprint;
+        }
         world.insert_resource(target);
     }
 }
 
+/// A server that answered our LAN discovery broadcast.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveredServer {
+    pub address: String,
+    /// Where the answer came from, the address is built from it.
+    pub source: IpAddr,
+    /// Everything the host advertised; hosts that only speak V1 leave all but the
+    /// port empty.
+    pub info: DiscoveryResponse,
+    /// Time between sending the broadcast probe and receiving this host's answer.
+    pub round_trip: Duration,
+    /// App time at which the host last answered, see [`Time::elapsed`].
+    pub last_seen: Duration,
+}
+
+impl DiscoveredServer {
+    /// The name to show in the server list, falling back to the address for V1 hosts.
+    pub fn display_name(&self) -> &str {
+        if self.info.server_name.is_empty() {
+            &self.address
+        } else {
+            &self.info.server_name
+        }
+    }
+
+    /// Hosts answer every probe that reaches them, once per interface and address family.
+    pub fn is_same_host(&self, other: &Self) -> bool {
+        match (&self.info.key_fingerprint, &other.info.key_fingerprint) {
+            (Some(hash), Some(other_hash)) => {
+                hash == other_hash && self.info.game_port == other.info.game_port
+            }
+            _ => self.address == other.address,
+        }
+    }
+
+    /// Lower is better. Link-local IPv6 comes last, it only reaches the host over one interface.
+    fn address_preference(&self) -> u8 {
+        match self.source {
+            IpAddr::V4(_) => 0,
+            IpAddr::V6(ip) if ip.is_unicast_link_local() => 2,
+            IpAddr::V6(_) => 1,
+        }
+    }
+
+    /// Adds `server` to `servers`, or merges it into an earlier answer from the same host.
+    pub fn merge_into(servers: &mut Vec<Self>, server: Self) {
+        let Some(known) = servers.iter_mut().find(|known| known.is_same_host(&server)) else {
+            servers.push(server);
+            return;
+        };
+        if server.address_preference() < known.address_preference() {
+            known.address = server.address;
+            known.source = server.source;
+        }
+        // Newer hosts answer both probes, keep the richer V2 answer. Otherwise
+        // take the latest one, the host may have rotated its identity.
+        if server.info.protocol_version >= known.info.protocol_version {
+            known.info = server.info;
+        }
+        // Answers to the same probe share `last_seen`, the first one measured the round trip.
+        known.round_trip = if server.last_seen == known.last_seen {
+            known.round_trip.min(server.round_trip)
+        } else {
+            server.round_trip
+        };
+        known.last_seen = server.last_seen;
+    }
+}
+
 #[derive(Resource, Default)]
-pub struct DiscoveredServers(pub Vec<String>);
+pub struct DiscoveredServers(pub Vec<DiscoveredServer>);
 
 #[derive(Component)]
-pub struct DiscoveryTask(Task<Vec<String>>);
+pub struct DiscoveryTask(Task<Vec<DiscoveredServer>>);
 
 #[derive(Resource)]
 pub struct DiscoveryTimer(pub Timer);
 
+/// How long a discovered server stays listed after its last answer.
+#[derive(Resource)]
+pub struct DiscoveredServerTtl(pub Duration);
+
+impl Default for DiscoveredServerTtl {
+    fn default() -> Self {
+        // Survives two missed probes at the default discovery interval.
+        Self(Duration::from_secs(6))
+    }
+}
+
 pub fn client_discover_server(
     mut commands: Commands,
     time: Res<Time>,
@@ -159,25 +526,40 @@ pub fn client_discover_server(
 
     let thread_pool = AsyncComputeTaskPool::get();
     let task = thread_pool.spawn(async move {
-        let socket = UdpSocket::bind(("0.0.0.0", 0)).expect("bind for discovery client");
-        socket.set_broadcast(true).expect("enable broadcast");
-        socket
-            .set_read_timeout(Some(Duration::from_millis(200)))
-            .ok();
+        // Probe both versions so hosts that only speak V1 still show up.
+        let mut probes = vec![REQUEST_MAGIC_V1.to_vec()];
+        if let Ok(request) = discovery::encode_request(&DiscoveryRequest {
+            protocol_version: PROTOCOL_VERSION,
+        }) {
+            probes.push(request);
+        }
 
-        let _ = socket.send_to(MAGIC, ("255.255.255.255", DISCOVERY_PORT));
+        let interfaces = if_addrs::get_if_addrs().unwrap_or_default();
+        let sent_at = Instant::now();
+        let sockets = helpers::send_discovery_probes(&interfaces, DISCOVERY_PORT, &probes);
 
-        let mut buf = [0u8; 256];
+        let mut buf = [0u8; MAX_PACKET_SIZE];
         let mut result = Vec::new();
-
-        while let Ok((len, src)) = socket.recv_from(&mut buf) {
-            let s = String::from_utf8_lossy(&buf[..len]);
-            if let Some(port_str) = s.strip_prefix("FORGE_RESP_V1;") {
-                if let Ok(port) = port_str.parse::<u16>() {
-                    let addr = format!("https://{}:{}", src.ip(), port);
-                    result.push(addr);
+        while sent_at.elapsed() < Duration::from_millis(200) {
+            let mut received = false;
+            for socket in &sockets {
+                while let Ok((len, src)) = socket.recv_from(&mut buf) {
+                    received = true;
+                    if let Some(info) = discovery::decode_response(&buf[..len]) {
+                        let server = DiscoveredServer {
+                            address: helpers::server_address(src, info.game_port),
+                            source: src.ip(),
+                            info,
+                            round_trip: sent_at.elapsed(),
+                            last_seen: Duration::ZERO,
+                        };
+                        DiscoveredServer::merge_into(&mut result, server);
+                    }
                 }
             }
+            if !received {
+                std::thread::sleep(Duration::from_millis(5));
+            }
         }
 
         result
@@ -188,30 +570,185 @@ pub fn client_discover_server(
 
 pub fn client_discover_server_collect(
     mut commands: Commands,
+    time: Res<Time>,
     mut discovered: ResMut<DiscoveredServers>,
     mut query: Query<(Entity, &mut DiscoveryTask)>,
 ) {
     for (entity, mut task) in &mut query {
         if let Some(result) = check_ready(&mut task.0) {
-            for server in result {
-                if !discovered.0.contains(&server) {
-                    discovered.0.push(server);
-                }
+            for mut server in result {
+                server.last_seen = time.elapsed();
+                DiscoveredServer::merge_into(&mut discovered.0, server);
             }
             commands.entity(entity).despawn();
         }
     }
 }
 
+pub fn client_discover_server_expire(
+    time: Res<Time>,
+    ttl: Res<DiscoveredServerTtl>,
+    mut discovered: ResMut<DiscoveredServers>,
+) {
+    let now = time.elapsed();
+    discovered
+        .0
+        .retain(|server| now.saturating_sub(server.last_seen) <= ttl.0);
+}
+
+pub fn clear_discovered_servers(
+    mut commands: Commands,
+    mut discovered: ResMut<DiscoveredServers>,
+    tasks: Query<Entity, With<DiscoveryTask>>,
+) {
+    discovered.0.clear();
+    // Dropping the task cancels it, so a late answer can't refill the list.
+    for task in &tasks {
+        commands.entity(task).despawn();
+    }
+}
+
+/// How long [`ClientStatus::Connecting`] waits for the session before giving up.
+///
+/// The transport already drops a silent server after [`crate::network_stats::MAX_IDLE_TIMEOUT`],
+/// this also catches servers that answer but never let us in.
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct ConnectTimeout(pub Duration);
+
+impl Default for ConnectTimeout {
+    fn default() -> Self {
+        Self(Duration::from_secs(10))
+    }
+}
+
+/// The connection being established, only present while [`ClientStatus::Connecting`].
+#[derive(Resource, Debug)]
+pub struct ConnectAttempt {
+    /// The address as typed in, for showing the player where we are connecting to.
+    pub input: String,
+    pub timeout: Timer,
+}
+
+impl ConnectAttempt {
+    pub fn remaining(&self) -> Duration {
+        self.timeout.remaining()
+    }
+}
+
+/// Gives up on the connection being established, ignored outside of [`ClientStatus::Connecting`].
+#[derive(Event, Debug, Clone, Copy)]
+pub struct CancelConnect;
+
 pub fn on_client_connecting(
     mut commands: Commands,
     client_target: Res<ClientTarget>,
-    mut cert_hash: Local<String>,
+    known_hosts: Res<KnownHosts>,
+    profile: Res<PlayerProfile>,
+    protocol: Res<ProtocolHash>,
+    timeout: Res<ConnectTimeout>,
     mut session_id: Local<usize>,
 ) {
-    let _cert_hash_resp = &mut *cert_hash;
-    let cert_hash = cert_hash.clone();
-    let config = match client_config(cert_hash) {
+    *session_id += 1;
+    commands.insert_resource(ConnectAttempt {
+        input: client_target.input.clone(),
+        timeout: Timer::new(timeout.0, TimerMode::Once),
+    });
+    let name = format!("{:#?}. {:?}", *session_id, client_target.input);
+    connect_to_target(
+        &mut commands,
+        &client_target,
+        &known_hosts,
+        &profile,
+        &protocol,
+        name,
+    );
+}
+
+pub fn client_connecting(
+    mut commands: Commands,
+    time: Res<Time>,
+    attempt: Option<ResMut<ConnectAttempt>>,
+    clients: Query<Entity, With<LocalClient>>,
+) {
+    let Some(mut attempt) = attempt else {
+        return;
+    };
+    if !attempt.timeout.tick(time.delta()).is_finished() {
+        return;
+    }
+
+    let seconds = attempt.timeout.duration().as_secs();
+    warn!(
+        "Connecting to {:?} timed out after {seconds}s",
+        attempt.input
+    );
+    commands.trigger(Notify::error(format!(
+        "Could not connect to {} within {seconds}s",
+        attempt.input
+    )));
+    abort_connecting(&mut commands, &clients, "connection timed out");
+}
+
+fn on_cancel_connect(
+    _trigger: On<CancelConnect>,
+    state: Option<Res<State<ClientStatus>>>,
+    clients: Query<Entity, With<LocalClient>>,
+    mut commands: Commands,
+) {
+    if state.is_none_or(|state| *state.get() != ClientStatus::Connecting) {
+        return;
+    }
+
+    info!("Connection canceled");
+    commands.trigger(Notify::info("Connection canceled"));
+    abort_connecting(&mut commands, &clients, "connection canceled");
+}
+
+/// Drops the pending session, aeronet despawns the client once it is disconnected.
+fn abort_connecting(
+    commands: &mut Commands,
+    clients: &Query<Entity, With<LocalClient>>,
+    reason: &str,
+) {
+    for client in clients {
+        commands.trigger(Disconnect::new(client, reason));
+    }
+    commands.trigger(SetClientStatus::Failed);
+}
+
+pub fn on_client_stop_connecting(mut commands: Commands) {
+    commands.remove_resource::<ConnectAttempt>();
+}
+
+fn connect_to_target(
+    commands: &mut Commands,
+    client_target: &ClientTarget,
+    known_hosts: &KnownHosts,
+    profile: &PlayerProfile,
+    protocol: &ProtocolHash,
+    name: String,
+) {
+    // A remembered key wins over the one a discovery answer or join link claims.
+    let address = host_key(client_target);
+    let expected = known_hosts
+        .get(&address)
+        .or((!client_target.key_fingerprint.is_empty())
+            .then_some(client_target.key_fingerprint.as_str()))
+        .map(str::to_string);
+    if expected.is_none() {
+        warn!("{address} is not a known server, trusting its key on first use");
+    }
+    let fingerprint = HostFingerprint {
+        address,
+        expected,
+        presented: PresentedCertificate::default(),
+    };
+
+    let config = match client_config(
+        fingerprint.expected.as_deref().unwrap_or_default(),
+        fingerprint.presented.clone(),
+        client_target.address,
+    ) {
         Ok(config) => config,
         Err(err) => {
             commands.trigger(Notify::error(format!(
@@ -221,27 +758,175 @@ pub fn on_client_connecting(
         }
     };
 
-    *session_id += 1;
-    let name = format!("{:#?}. {:?}", *session_id, client_target.input);
     info!("Connecting to server at {:?}", client_target.input);
     commands
-        .spawn((Name::new(name), LocalClient, AeronetRepliconClient))
+        .spawn((
+            Name::new(name),
+            LocalClient,
+            AeronetRepliconClient,
+            fingerprint,
+        ))
         .queue(WebTransportClient::connect(
             config,
-            client_target.real_address.clone(),
+            helpers::connect_options(client_target, profile, protocol),
         ))
         .observe(on_client_connected)
         .observe(on_client_connection_failed)
         .observe(on_client_disconnected);
 }
 
+/// Limits for retrying a connection that was lost while playing.
+#[derive(Resource, Debug, Clone)]
+pub struct ReconnectPolicy {
+    /// Attempts before giving up and going back to the menu.
+    pub max_attempts: u32,
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Wait before the given attempt (starting at 1), doubling each time.
+    pub fn delay_before(&self, attempt: u32) -> Duration {
+        let doublings = attempt.saturating_sub(1).min(16);
+        self.initial_delay
+            .saturating_mul(1 << doublings)
+            .min(self.max_delay)
+    }
+}
+
+/// Progress of the current reconnect, only present while [`ClientStatus::Reconnecting`].
+#[derive(Resource, Debug)]
+pub struct ReconnectState {
+    /// The attempt that is running or waiting to run, starting at 1.
+    pub attempt: u32,
+    pub max_attempts: u32,
+    pub delay: Timer,
+    /// Whether a connection for [`Self::attempt`] is currently being established.
+    pub in_flight: bool,
+}
+
+pub fn on_client_start_reconnecting(mut commands: Commands, policy: Res<ReconnectPolicy>) {
+    commands.insert_resource(ReconnectState {
+        attempt: 1,
+        max_attempts: policy.max_attempts,
+        delay: Timer::new(policy.delay_before(1), TimerMode::Once),
+        in_flight: false,
+    });
+}
+
+pub fn on_client_stop_reconnecting(mut commands: Commands) {
+    commands.remove_resource::<ReconnectState>();
+}
+
+pub fn client_reconnecting(
+    mut commands: Commands,
+    time: Res<Time>,
+    client_target: Res<ClientTarget>,
+    known_hosts: Res<KnownHosts>,
+    profile: Res<PlayerProfile>,
+    protocol: Res<ProtocolHash>,
+    reconnect: Option<ResMut<ReconnectState>>,
+) {
+    let Some(mut reconnect) = reconnect else {
+        return;
+    };
+    if reconnect.in_flight || !reconnect.delay.tick(time.delta()).is_finished() {
+        return;
+    }
+
+    info!(
+        "Reconnect attempt {}/{}",
+        reconnect.attempt, reconnect.max_attempts
+    );
+    reconnect.in_flight = true;
+    let name = format!("Reconnect {}. {:?}", reconnect.attempt, client_target.input);
+    connect_to_target(
+        &mut commands,
+        &client_target,
+        &known_hosts,
+        &profile,
+        &protocol,
+        name,
+    );
+}
+
+/// Schedules the next attempt after a failed one, or gives up once the policy is exhausted.
+fn on_reconnect_attempt_failed(
+    commands: &mut Commands,
+    reconnect: &mut ReconnectState,
+    policy: &ReconnectPolicy,
+    reason: &DisconnectReason,
+) {
+    warn!("Reconnect attempt {} failed: {reason:?}", reconnect.attempt);
+    reconnect.in_flight = false;
+
+    // The server answering with a reason means it is up but won't have us back.
+    if reconnect.attempt >= reconnect.max_attempts || matches!(reason, DisconnectReason::ByPeer(_))
+    {
+        let detail = match reason {
+            DisconnectReason::ByPeer(msg) => format!(": {msg}"),
+            _ => String::new(),
+        };
+        commands.trigger(Notify::error(format!(
+            "Could not reconnect after {} attempts{detail}",
+            reconnect.attempt
+        )));
+        commands.trigger(SetClientStatus::Failed);
+        return;
+    }
+
+    reconnect.attempt += 1;
+    reconnect.delay = Timer::new(policy.delay_before(reconnect.attempt), TimerMode::Once);
+}
+
 fn on_client_connection_failed(
     trigger: On<Disconnected>,
     current_state: Option<Res<State<ClientStatus>>>,
     mut commands: Commands,
     mut client_target: ResMut<ClientTarget>,
+    reconnect: Option<ResMut<ReconnectState>>,
+    policy: Res<ReconnectPolicy>,
+    fingerprints: Query<&HostFingerprint>,
 ) {
+    // A changed certificate is no network trouble, retrying or blaming the connection won't help.
+    if let Some(mismatch) = fingerprints
+        .get(trigger.event_target())
+        .ok()
+        .and_then(HostFingerprint::mismatch)
+    {
+        error!(
+            "Certificate of {} changed from {} to {}",
+            mismatch.address, mismatch.expected, mismatch.presented
+        );
+        commands.trigger(Notify::error(mismatch.describe()));
+        commands.insert_resource(mismatch);
+        client_target.is_valid = false;
+        commands.trigger(SetClientStatus::Failed);
+        return;
+    }
+
     if let Some(current_state) = current_state {
+        if *current_state.get() == ClientStatus::Reconnecting {
+            if let Some(mut reconnect) = reconnect {
+                on_reconnect_attempt_failed(
+                    &mut commands,
+                    &mut reconnect,
+                    &policy,
+                    &trigger.reason,
+                );
+            }
+            return;
+        }
         if *current_state.get() == ClientStatus::Connecting {
             match &trigger.reason {
                 DisconnectReason::ByError(err) => {
@@ -250,12 +935,8 @@ fn on_client_connection_failed(
                     client_target.is_valid = false;
                     commands.trigger(SetClientStatus::Failed);
                 }
-                DisconnectReason::ByUser(err) => {
-                    error!("Connection Error: {}", err);
-                    commands.trigger(Notify::error(format!("Connection Error: {}", err)));
-                    client_target.is_valid = false;
-                    commands.trigger(SetClientStatus::Failed);
-                }
+                // Canceled or timed out, `abort_connecting` already moved on.
+                DisconnectReason::ByUser(_) => {}
                 DisconnectReason::ByPeer(err) => {
                     error!("Connection Error: {}", err);
                     commands.trigger(Notify::error(format!("Connection Error: {}", err)));
@@ -267,21 +948,122 @@ fn on_client_connection_failed(
     }
 }
 
-pub fn on_client_connected(trigger: On<Add, Session>, names: Query<&Name>, mut commands: Commands) {
+pub fn on_client_connected(
+    trigger: On<Add, Session>,
+    names: Query<&Name>,
+    reconnect: Option<Res<ReconnectState>>,
+    mut commands: Commands,
+) {
     let target = trigger.event_target();
 
+    if let Some(reconnect) = reconnect {
+        commands.trigger(Notify::success(format!(
+            "Reconnected after {} attempt(s)",
+            reconnect.attempt
+        )));
+    }
+
     let name = names.get(target).ok();
     if let Some(name) = name {
         info!("Connected as {}", name.as_str());
     } else {
         warn!("Session {} missing Name component", target);
     }
+    commands.trigger(SetClientStatus::Transition(ClientStatus::Connected));
+}
+
+/// How long [`ClientStatus::Syncing`] waits for the initial world before giving up.
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct SyncTimeout(pub Duration);
+
+impl Default for SyncTimeout {
+    fn default() -> Self {
+        Self(Duration::from_secs(30))
+    }
+}
+
+/// How far the initial world got, present from [`ClientStatus::Connected`] until
+/// [`ClientStatus::Syncing`] is over.
+#[derive(Resource, Debug)]
+pub struct SyncProgress {
+    /// Replicated entities that arrived in this session so far.
+    pub received: usize,
+    /// Entities the server announced with [`SyncComplete`], `None` until it arrives. Only
+    /// for showing progress, some of them may be despawned or hidden before they reach us.
+    pub expected: Option<usize>,
+    pub timeout: Timer,
+}
+
+/// The session is up, replicon still has to take it over before the world can arrive.
+pub fn on_client_enter_connected(mut commands: Commands, timeout: Res<SyncTimeout>) {
+    // Inserted here and not when syncing starts, so a quick `SyncComplete` can't slip by.
+    commands.insert_resource(SyncProgress {
+        received: 0,
+        expected: None,
+        timeout: Timer::new(timeout.0, TimerMode::Once),
+    });
+}
+
+/// Replicon reports the connection, from now on the server replicates to us.
+pub fn client_connected(mut commands: Commands) {
+    info!("Replication connected, waiting for the world");
     commands.trigger(SetClientStatus::Transition(ClientStatus::Syncing));
 }
 
-pub fn client_syncing(mut commands: Commands) {
-    info!("TODO: Implement client sync system");
-    commands.trigger(SetClientStatus::Transition(ClientStatus::Running));
+fn receive_sync_complete(
+    mut sync_messages: MessageReader<SyncComplete>,
+    mut progress: ResMut<SyncProgress>,
+) {
+    for sync in sync_messages.read() {
+        progress.expected = Some(sync.entities);
+    }
+}
+
+/// Counts what the server replicated since [`SyncProgress`] was inserted, earlier entities
+/// belong to another session. Runs every frame so `Added` never reaches further back.
+fn count_received_entities(
+    progress: Option<ResMut<SyncProgress>>,
+    received: Query<(), Added<Replicated>>,
+) {
+    if let Some(mut progress) = progress {
+        progress.received += received.iter().count();
+    }
+}
+
+pub fn client_syncing(
+    mut commands: Commands,
+    time: Res<Time>,
+    progress: Option<ResMut<SyncProgress>>,
+) {
+    let Some(mut progress) = progress else {
+        return;
+    };
+
+    // The marker only arrives after the initial world, whatever the count says.
+    if let Some(expected) = progress.expected {
+        info!(
+            "Synced {} entities (server announced {expected})",
+            progress.received
+        );
+        commands.trigger(SetClientStatus::Transition(ClientStatus::Running));
+        return;
+    }
+
+    if progress.timeout.tick(time.delta()).is_finished() {
+        let seconds = progress.timeout.duration().as_secs();
+        warn!(
+            "Syncing timed out after {seconds}s with {} entities",
+            progress.received
+        );
+        commands.trigger(Notify::error(format!(
+            "The server did not send the world within {seconds}s"
+        )));
+        commands.trigger(SetClientStatus::Transition(ClientStatus::Disconnecting));
+    }
+}
+
+pub fn on_client_stop_syncing(mut commands: Commands) {
+    commands.remove_resource::<SyncProgress>();
 }
 
 pub fn on_client_start_disconnecting(mut commands: Commands) {
@@ -317,60 +1099,827 @@ pub fn client_disconnecting(
 
 pub mod helpers {
     use {
-        aeronet_webtransport::{cert, client::ClientConfig, wtransport::tls::Sha256Digest},
+        super::{
+            known_hosts::{host_key, PresentedCertificate, RecordingVerifier},
+            ClientTarget,
+        },
+        crate::{
+            network_stats::{KEEP_ALIVE_INTERVAL, MAX_IDLE_TIMEOUT},
+            player::PlayerProfile,
+            protocol::{
+                discovery::{MULTICAST_V4, MULTICAST_V6},
+                handshake::{
+                    self, GAME_VERSION_HEADER, PASSWORD_HEADER, PLAYER_ID_HEADER,
+                    PLAYER_NAME_HEADER, PROTOCOL_HASH_HEADER, PROTOCOL_VERSION_HEADER,
+                },
+                GAME_PORT,
+            },
+        },
+        aeronet_webtransport::{
+            cert,
+            client::ClientConfig,
+            wtransport::{
+                config::{DnsLookupFuture, DnsResolver},
+                endpoint::ConnectOptions,
+                tls::{client::build_default_tls_config, rustls::RootCertStore},
+            },
+        },
+        anyhow::Context,
         bevy::prelude::*,
-        core::time::Duration,
-        std::net::SocketAddr,
+        bevy_replicon::prelude::ProtocolHash,
+        if_addrs::{IfAddr, IfOperStatus, Interface},
+        socket2::{Domain, Protocol, SockRef, Socket, Type},
+        std::{
+            net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6, UdpSocket},
+            pin::Pin,
+            sync::Arc,
+        },
     };
 
-    // TODO: Remove anyhow here
-    pub(super) fn client_config(cert_hash: String) -> Result<ClientConfig, anyhow::Error> {
-        let config = ClientConfig::builder().with_bind_default();
-
-        let config = if cert_hash.is_empty() {
-            #[cfg(debug_assertions)]
-            {
-                warn!("Connecting with no certificate validation");
-                config.with_no_cert_validation()
+    /// Stands in for a link-local address in the session URL, URLs have no room for its zone.
+    /// The reserved `.invalid` name never reaches a real resolver, see [`ResolvedAddress`].
+    pub const LINK_LOCAL_HOST: &str = "link-local.invalid";
+
+    fn usable(interface: &&Interface) -> bool {
+        !interface.is_loopback() && interface.oper_status != IfOperStatus::Down
+    }
+
+    /// Where discovery probes go, apart from the IPv4 multicast group: the limited broadcast,
+    /// the directed broadcast of every IPv4 interface and the IPv6 group on every interface.
+    pub fn discovery_targets(interfaces: &[Interface], port: u16) -> Vec<SocketAddr> {
+        let mut targets = vec![SocketAddr::from((Ipv4Addr::BROADCAST, port))];
+        for interface in interfaces.iter().filter(usable) {
+            let target = match (&interface.addr, interface.index) {
+                (IfAddr::V4(addr), _) => match addr.broadcast {
+                    Some(broadcast) => SocketAddr::from((broadcast, port)),
+                    None => continue,
+                },
+                // Link-local multicast needs the interface, it is taken from the scope.
+                (IfAddr::V6(_), Some(index)) => {
+                    SocketAddr::V6(SocketAddrV6::new(MULTICAST_V6, port, 0, index))
+                }
+                (IfAddr::V6(_), None) => continue,
+            };
+            if !targets.contains(&target) {
+                targets.push(target);
+            }
+        }
+        targets
+    }
+
+    /// Sends every probe to every target, returns the non-blocking sockets answers arrive on.
+    pub fn send_discovery_probes(
+        interfaces: &[Interface],
+        port: u16,
+        probes: &[Vec<u8>],
+    ) -> Vec<UdpSocket> {
+        let targets = discovery_targets(interfaces, port);
+        let send = |socket: &UdpSocket, target: SocketAddr| {
+            for probe in probes {
+                let _ = socket.send_to(probe, target);
+            }
+        };
+
+        let mut sockets = Vec::new();
+        match UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)) {
+            Ok(socket) => {
+                let _ = socket.set_broadcast(true);
+                targets
+                    .iter()
+                    .filter(|target| target.is_ipv4())
+                    .for_each(|target| send(&socket, *target));
+                // Multicast leaves through the default route unless told otherwise.
+                let multicast = SocketAddr::from((MULTICAST_V4, port));
+                send(&socket, multicast);
+                for interface in interfaces.iter().filter(usable) {
+                    if let IfAddr::V4(addr) = &interface.addr {
+                        if SockRef::from(&socket).set_multicast_if_v4(&addr.ip).is_ok() {
+                            send(&socket, multicast);
+                        }
+                    }
+                }
+                sockets.push(socket);
+            }
+            Err(err) => warn!("Could not bind the IPv4 discovery socket: {err}"),
+        }
+        match bind_probe_socket_v6() {
+            Ok(socket) => {
+                targets
+                    .iter()
+                    .filter(|target| target.is_ipv6())
+                    .for_each(|target| send(&socket, *target));
+                sockets.push(socket);
+            }
+            Err(err) => debug!("No IPv6 discovery: {err}"),
+        }
+
+        sockets.retain(|socket| socket.set_nonblocking(true).is_ok());
+        sockets
+    }
+
+    fn bind_probe_socket_v6() -> std::io::Result<UdpSocket> {
+        let socket = Socket::new(Domain::IPV6, Type::DGRAM, Some(Protocol::UDP))?;
+        socket.set_only_v6(true)?;
+        socket.bind(&SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)).into())?;
+        Ok(socket.into())
+    }
+
+    /// The IP of `address` as it is typed in, with the zone of a scoped IPv6 address.
+    pub fn host_of(address: SocketAddr) -> String {
+        match address {
+            SocketAddr::V6(address) if address.scope_id() != 0 => {
+                format!("{}%{}", address.ip(), address.scope_id())
             }
-            #[cfg(not(debug_assertions))]
-            {
-                config.with_server_certificate_hashes([])
+            address => address.ip().to_string(),
+        }
+    }
+
+    /// URL for the session request to `address`.
+    pub fn session_url(address: SocketAddr) -> String {
+        match address {
+            SocketAddr::V6(address) if address.scope_id() != 0 => {
+                format!("https://{LINK_LOCAL_HOST}:{}", address.port())
             }
+            address => format!("https://{address}"),
+        }
+    }
+
+    /// Answers wtransport's lookup of [`LINK_LOCAL_HOST`] with the address we resolved
+    /// ourselves, zone included. Every other URL carries its IP already.
+    #[derive(Debug)]
+    struct ResolvedAddress(SocketAddr);
+
+    impl DnsResolver for ResolvedAddress {
+        fn resolve(&self, _host: &str) -> Pin<Box<dyn DnsLookupFuture>> {
+            let address = self.0;
+            Box::pin(async move { Ok(Some(address)) })
+        }
+    }
+
+    /// URL of the game port of the host that answered from `source`.
+    pub fn server_address(source: SocketAddr, game_port: u16) -> String {
+        let mut address = source;
+        address.set_port(game_port);
+        format!("https://{address}")
+    }
+
+    /// Session request for `target`, carrying the handshake headers the server checks.
+    pub(super) fn connect_options(
+        target: &ClientTarget,
+        profile: &PlayerProfile,
+        protocol: &ProtocolHash,
+    ) -> ConnectOptions {
+        let mut options = ConnectOptions::builder(&target.real_address)
+            .add_header(
+                PROTOCOL_VERSION_HEADER,
+                handshake::PROTOCOL_VERSION.to_string(),
+            )
+            .add_header(
+                PROTOCOL_HASH_HEADER,
+                handshake::encode_protocol_hash(protocol),
+            )
+            .add_header(GAME_VERSION_HEADER, env!("CARGO_PKG_VERSION"))
+            .add_header(
+                PLAYER_ID_HEADER,
+                profile.id_for(&host_key(target)).to_string(),
+            )
+            .add_header(PLAYER_NAME_HEADER, &profile.name);
+        if !target.password.is_empty() {
+            options = options.add_header(PASSWORD_HEADER, &target.password);
+        }
+        options.build()
+    }
+
+    // TODO: Remove anyhow here
+    /// Pins the server key to the SPKI `key_fingerprint`, or trusts it on first use when empty.
+    /// The hash the server actually presents ends up in `presented`, the session goes to
+    /// `address` whatever host its URL names.
+    pub(super) fn client_config(
+        key_fingerprint: &str,
+        presented: PresentedCertificate,
+        address: Option<SocketAddr>,
+    ) -> Result<ClientConfig, anyhow::Error> {
+        let pinned = if key_fingerprint.is_empty() {
+            None
         } else {
-            let hash = cert::hash_from_b64(&cert_hash)?;
-            config.with_server_certificate_hashes([Sha256Digest::new(hash)])
+            cert::digest_from_spki_fingerprint(key_fingerprint)
+                .context("the key fingerprint is not a base64 SHA-256 digest")?;
+            Some(key_fingerprint.to_string())
         };
+        let verifier = RecordingVerifier::new(pinned, presented);
+        let tls =
+            build_default_tls_config(Arc::new(RootCertStore::empty()), Some(Arc::new(verifier)));
 
-        Ok(config
-            .keep_alive_interval(Some(Duration::from_secs(1)))
-            .max_idle_timeout(Some(Duration::from_secs(5)))
-            .expect("should be a valid idle timeout")
-            .build())
+        let builder = ClientConfig::builder()
+            .with_bind_default()
+            .with_custom_tls(tls)
+            .keep_alive_interval(Some(KEEP_ALIVE_INTERVAL))
+            .max_idle_timeout(Some(MAX_IDLE_TIMEOUT))
+            .expect("should be a valid idle timeout");
+        Ok(match address {
+            Some(address) => builder.dns_resolver(ResolvedAddress(address)).build(),
+            None => builder.build(),
+        })
     }
 
-    pub fn parse_target_live(input: &str) -> Option<(String, u16)> {
+    /// Host part of a server address, before any name lookup.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum TargetHost {
+        Ip(IpAddr),
+        Name(String),
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct ParsedTarget {
+        pub host: TargetHost,
+        pub port: u16,
+        /// Interface index from the zone of an IPv6 address like `[fe80::1%3]`, 0 without one.
+        pub scope_id: u32,
+    }
+
+    /// Parses `host[:port]`, `[ipv6[%zone]][:port]` or a bare IP, with an optional `http(s)://`.
+    /// Without a port the default [`GAME_PORT`] is used.
+    pub fn parse_target(input: &str) -> Result<ParsedTarget, String> {
+        let input = input.trim();
         let input = input
-            .trim()
             .strip_prefix("https://")
-            .unwrap_or(input)
-            .strip_prefix("http://")
-            .unwrap_or(input)
-            .trim();
-
+            .or_else(|| input.strip_prefix("http://"))
+            .unwrap_or(input);
+        let input = input.strip_suffix('/').unwrap_or(input);
         if input.is_empty() {
-            return None;
+            return Err("Enter a server address".to_string());
         }
 
-        let addr: SocketAddr = match input.parse() {
-            Ok(addr) => addr,
-            Err(_) => return None,
+        let mut scope_id = 0;
+        let (host, port) = if let Some(rest) = input.strip_prefix('[') {
+            let (ip, rest) = rest
+                .split_once(']')
+                .ok_or("Missing ] after the IPv6 address")?;
+            // Discovery lists link-local hosts with the index of the interface that reached them.
+            let ip = match ip.split_once('%') {
+                Some((ip, zone)) => {
+                    scope_id = zone
+                        .parse::<u32>()
+                        .ok()
+                        .filter(|index| *index != 0)
+                        .ok_or_else(|| {
+                            format!("The zone {zone:?} is not an interface index, like %3")
+                        })?;
+                    ip
+                }
+                None => ip,
+            };
+            let ip = ip
+                .parse::<Ipv6Addr>()
+                .map_err(|_| format!("{ip:?} is not an IPv6 address"))?;
+            let port = match rest {
+                "" => None,
+                _ => Some(
+                    rest.strip_prefix(':')
+                        .ok_or("Expected :port after the IPv6 address")?,
+                ),
+            };
+            (TargetHost::Ip(ip.into()), port)
+        } else if let Ok(ip) = input.parse::<IpAddr>() {
+            // A bare IPv6 address has colons of its own, it can't carry a port.
+            (TargetHost::Ip(ip), None)
+        } else {
+            let (host, port) = match input.rsplit_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (input, None),
+            };
+            if host.contains(':') {
+                return Err("Put IPv6 addresses in brackets, like [::1]:25571".to_string());
+            }
+            match host.parse::<IpAddr>() {
+                Ok(ip) => (TargetHost::Ip(ip), port),
+                Err(_) if is_hostname(host) => (TargetHost::Name(host.to_lowercase()), port),
+                Err(_) => return Err(format!("{host:?} is not a hostname or IP address")),
+            }
+        };
+
+        let port = match port {
+            None => GAME_PORT,
+            Some(port) => port
+                .parse::<u16>()
+                .ok()
+                .filter(|port| *port != 0)
+                .ok_or_else(|| format!("{port:?} is not a valid port"))?,
+        };
+        Ok(ParsedTarget {
+            host,
+            port,
+            scope_id,
+        })
+    }
+
+    /// Letters, digits and hyphens in dot separated labels, as DNS allows them.
+    fn is_hostname(host: &str) -> bool {
+        let host = host.strip_suffix('.').unwrap_or(host);
+        !host.is_empty()
+            && host.len() <= 253
+            && host.split('.').all(|label| {
+                (1..=63).contains(&label.len())
+                    && !label.starts_with('-')
+                    && !label.ends_with('-')
+                    && label
+                        .chars()
+                        .all(|char| char.is_ascii_alphanumeric() || char == '-')
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            notifications::{NotificationQueue, NotificationType},
+            player::Player,
+        },
+        std::net::SocketAddr,
+    };
+
+    #[test]
+    fn reconnect_delay_doubles_up_to_the_limit() {
+        let policy = ReconnectPolicy {
+            max_attempts: 10,
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(10),
+        };
+
+        let delays: Vec<u64> = (1..=6)
+            .map(|attempt| policy.delay_before(attempt).as_secs())
+            .collect();
+        assert_eq!(delays, [1, 2, 4, 8, 10, 10]);
+        assert_eq!(policy.delay_before(u32::MAX), Duration::from_secs(10));
+    }
+
+    fn interface(addr: if_addrs::IfAddr, index: u32) -> if_addrs::Interface {
+        if_addrs::Interface {
+            name: format!("eth{index}"),
+            addr,
+            index: Some(index),
+            oper_status: if_addrs::IfOperStatus::Up,
+            is_p2p: false,
+        }
+    }
+
+    #[test]
+    fn probes_reach_every_interface() {
+        use {
+            crate::protocol::discovery::MULTICAST_V6,
+            if_addrs::{IfAddr, Ifv4Addr, Ifv6Addr},
+            std::net::{Ipv4Addr, Ipv6Addr, SocketAddrV6},
+        };
+
+        let v4 = |ip: [u8; 4], broadcast: Option<[u8; 4]>| {
+            IfAddr::V4(Ifv4Addr {
+                ip: ip.into(),
+                netmask: Ipv4Addr::new(255, 255, 255, 0),
+                prefixlen: 24,
+                broadcast: broadcast.map(Ipv4Addr::from),
+            })
+        };
+        let interfaces = [
+            interface(v4([127, 0, 0, 1], None), 1),
+            interface(v4([192, 168, 1, 10], Some([192, 168, 1, 255])), 2),
+            interface(v4([10, 0, 0, 5], Some([10, 0, 0, 255])), 3),
+            interface(
+                IfAddr::V6(Ifv6Addr {
+                    ip: "fe80::1".parse().unwrap(),
+                    netmask: Ipv6Addr::UNSPECIFIED,
+                    prefixlen: 64,
+                    broadcast: None,
+                }),
+                3,
+            ),
+        ];
+
+        assert_eq!(
+            helpers::discovery_targets(&interfaces, 30000),
+            [
+                SocketAddr::from(([255, 255, 255, 255], 30000)),
+                SocketAddr::from(([192, 168, 1, 255], 30000)),
+                SocketAddr::from(([10, 0, 0, 255], 30000)),
+                SocketAddr::V6(SocketAddrV6::new(MULTICAST_V6, 30000, 0, 3)),
+            ]
+        );
+    }
+
+    #[test]
+    fn answers_from_one_host_collapse_into_one_entry() {
+        let answer = |source: &str, round_trip: u64| {
+            let source: SocketAddr = source.parse().unwrap();
+            DiscoveredServer {
+                address: helpers::server_address(source, 25571),
+                source: source.ip(),
+                info: DiscoveryResponse {
+                    protocol_version: PROTOCOL_VERSION,
+                    game_port: 25571,
+                    key_fingerprint: Some("aGFzaA==".to_string()),
+                    ..default()
+                },
+                round_trip: Duration::from_millis(round_trip),
+                last_seen: Duration::ZERO,
+            }
         };
 
-        if addr.port() == 0 {
-            return None;
+        let mut servers = Vec::new();
+        DiscoveredServer::merge_into(&mut servers, answer("[fe80::1%3]:30000", 4));
+        DiscoveredServer::merge_into(&mut servers, answer("192.168.1.10:30000", 6));
+        DiscoveredServer::merge_into(&mut servers, answer("10.0.0.5:30000", 9));
+        assert_eq!(servers.len(), 1);
+        assert_eq!(servers[0].address, "https://192.168.1.10:25571");
+        assert_eq!(servers[0].round_trip, Duration::from_millis(4));
+
+        let mut other = answer("192.168.1.11:30000", 5);
+        other.info.key_fingerprint = Some("b3RoZXI=".to_string());
+        DiscoveredServer::merge_into(&mut servers, other);
+        assert_eq!(servers.len(), 2);
+    }
+
+    #[test]
+    fn targets_accept_hostnames_ipv6_and_a_default_port() {
+        use {
+            crate::protocol::GAME_PORT,
+            helpers::{parse_target, ParsedTarget, TargetHost},
+        };
+
+        let target = |host: TargetHost, port: u16| {
+            Ok(ParsedTarget {
+                host,
+                port,
+                scope_id: 0,
+            })
+        };
+        let ip = |ip: &str| TargetHost::Ip(ip.parse().unwrap());
+        let name = |name: &str| TargetHost::Name(name.to_string());
+
+        assert_eq!(
+            parse_target("192.168.1.10"),
+            target(ip("192.168.1.10"), GAME_PORT)
+        );
+        assert_eq!(
+            parse_target("https://10.0.0.5:26000/"),
+            target(ip("10.0.0.5"), 26000)
+        );
+        assert_eq!(parse_target("[::1]:26000"), target(ip("::1"), 26000));
+        assert_eq!(
+            parse_target("[2001:db8::1]"),
+            target(ip("2001:db8::1"), GAME_PORT)
+        );
+        assert_eq!(
+            parse_target("2001:db8::1"),
+            target(ip("2001:db8::1"), GAME_PORT)
+        );
+        assert_eq!(
+            parse_target("MyHost.lan:25571"),
+            target(name("myhost.lan"), 25571)
+        );
+        assert_eq!(
+            parse_target("localhost"),
+            target(name("localhost"), GAME_PORT)
+        );
+
+        assert!(parse_target("myhost:0").is_err());
+        assert!(parse_target("myhost:http").is_err());
+        assert!(parse_target("my host").is_err());
+        assert!(parse_target("[::1").is_err());
+        assert_eq!(
+            parse_target("https://[fe80::1%3]:25571"),
+            Ok(ParsedTarget {
+                host: ip("fe80::1"),
+                port: 25571,
+                scope_id: 3,
+            })
+        );
+        assert!(parse_target("[fe80::1%eth0]:25571").is_err());
+        assert!(parse_target("[fe80::1%0]").is_err());
+    }
+
+    #[test]
+    fn hostnames_resolve_on_the_task_pool() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .init_resource::<ClientTarget>()
+            .add_systems(
+                Update,
+                (client_resolve_target, client_resolve_target_collect).chain(),
+            );
+
+        let mut target = ClientTarget::default();
+        target.update_input("localhost:26000".to_string());
+        assert_eq!(target.status, TargetStatus::Resolving);
+        assert!(!target.is_valid);
+        app.insert_resource(target);
+
+        for _ in 0..200 {
+            app.update();
+            if app.world().resource::<ClientTarget>().status != TargetStatus::Resolving {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(5));
         }
+        let target = app.world().resource::<ClientTarget>();
+        assert_eq!(target.status, TargetStatus::Resolved);
+        assert!(target.is_valid);
+        assert!(target.ip.parse::<IpAddr>().unwrap().is_loopback());
+        assert!(target.real_address.ends_with(":26000"));
+
+        let mut target = ClientTarget::default();
+        target.update_input("my host".to_string());
+        assert!(matches!(target.status, TargetStatus::Invalid(_)));
+    }
+
+    #[test]
+    fn join_links_pin_the_certificate_hash() {
+        let hash = "r/lhrD9QqJdT/5Cq5eJyA8UMRxYiozquZ2npB0D6y5U=";
+        let link = join_link::encode("192.168.1.10:26000".parse().unwrap(), hash);
 
-        Some((addr.ip().to_string(), addr.port()))
+        let mut target = ClientTarget::default();
+        target.update_input(link);
+        assert_eq!(target.status, TargetStatus::Resolved);
+        assert_eq!(target.real_address, "https://192.168.1.10:26000");
+        assert_eq!(target.key_fingerprint, hash);
+        assert!(helpers::client_config(&target.key_fingerprint, default(), target.address).is_ok());
+
+        target.update_input("fos://192.168.1.10:26000#bm90LWEtaGFzaA".to_string());
+        assert!(matches!(target.status, TargetStatus::Invalid(_)));
+        assert!(target.key_fingerprint.is_empty());
+
+        // Typing over a link drops its hash.
+        target.update_input("192.168.1.10:26000".to_string());
+        assert!(target.is_valid && target.key_fingerprint.is_empty());
+    }
+
+    #[test]
+    fn each_server_gets_its_own_player_id() {
+        let profile = PlayerProfile::default();
+        let protocol = *crate::test_app().world().resource::<ProtocolHash>();
+        let sent_id = |input: &str| {
+            let mut target = ClientTarget::default();
+            target.update_input(input.to_string());
+            helpers::connect_options(&target, &profile, &protocol).additional_headers()
+                [crate::protocol::handshake::PLAYER_ID_HEADER]
+                .clone()
+        };
+
+        let first = sent_id("192.168.1.10:26000");
+        assert_eq!(first, profile.id_for("192.168.1.10:26000").to_string());
+        assert_eq!(sent_id("192.168.1.10:26000"), first);
+        assert_ne!(sent_id("192.168.1.11:26000"), first);
+        assert_ne!(first, profile.secret.to_string());
+    }
+
+    #[test]
+    fn link_local_servers_can_be_picked_from_discovery() {
+        let source: SocketAddr = "[fe80::1%3]:30000".parse().unwrap();
+        let server = DiscoveredServer {
+            address: helpers::server_address(source, 25571),
+            source: source.ip(),
+            info: DiscoveryResponse {
+                protocol_version: PROTOCOL_VERSION,
+                game_port: 25571,
+                key_fingerprint: Some("r/lhrD9QqJdT/5Cq5eJyA8UMRxYiozquZ2npB0D6y5U=".to_string()),
+                ..default()
+            },
+            round_trip: Duration::from_millis(1),
+            last_seen: Duration::ZERO,
+        };
+        assert_eq!(server.address, "https://[fe80::1%3]:25571");
+
+        let mut world = World::new();
+        SetClientTarget {
+            input: server.address.clone(),
+            key_fingerprint: server.info.key_fingerprint.clone(),
+        }
+        .apply(&mut world);
+
+        let target = world.resource::<ClientTarget>();
+        assert_eq!(target.status, TargetStatus::Resolved);
+        assert!(target.is_valid);
+        assert_eq!(target.address, Some("[fe80::1%3]:25571".parse().unwrap()));
+        assert_eq!(target.real_address, "https://link-local.invalid:25571");
+        assert_eq!(host_key(target), "[fe80::1%3]:25571");
+        assert!(helpers::client_config(&target.key_fingerprint, default(), target.address).is_ok());
+    }
+
+    fn join_game_app(timeout: Duration) -> App {
+        use crate::status_management::{
+            MainMenuContext, MainMenuInteraction, SetJoinGame, SetMultiplayerMenu,
+        };
+
+        let mut app = crate::test_app();
+        app.insert_resource(ConnectTimeout(timeout));
+
+        app.world_mut().trigger(MainMenuInteraction::SwitchContext(
+            MainMenuContext::Multiplayer,
+        ));
+        app.update();
+        app.world_mut()
+            .trigger(SetMultiplayerMenu::Navigate(MultiplayerSetup::JoinGame));
+        app.update();
+        // Nothing listens there, the attempt can only end by canceling or timing out.
+        app.world_mut().commands().queue(SetClientTarget {
+            input: "127.0.0.1:9".to_string(),
+            key_fingerprint: None,
+        });
+        app.update();
+        app.world_mut().trigger(SetJoinGame::Confirm);
+        app.update();
+        app.update();
+        app
+    }
+
+    fn client_status(app: &App) -> Option<ClientStatus> {
+        app.world()
+            .get_resource::<State<ClientStatus>>()
+            .map(|state| *state.get())
+    }
+
+    fn local_clients(app: &mut App) -> usize {
+        app.world_mut()
+            .query_filtered::<(), With<LocalClient>>()
+            .iter(app.world())
+            .count()
+    }
+
+    #[test]
+    fn connecting_can_be_canceled_or_time_out() {
+        let mut app = join_game_app(Duration::from_secs(60));
+        assert_eq!(client_status(&app), Some(ClientStatus::Connecting));
+        assert!(app.world().contains_resource::<ConnectAttempt>());
+        assert_eq!(local_clients(&mut app), 1);
+
+        app.world_mut().trigger(CancelConnect);
+        app.update();
+        app.update();
+        assert_eq!(client_status(&app), None);
+        assert!(!app.world().contains_resource::<ConnectAttempt>());
+        assert_eq!(local_clients(&mut app), 0);
+        let notes = &app.world().resource::<NotificationQueue>().messages;
+        assert!(notes
+            .iter()
+            .all(|note| note.type_ != NotificationType::Error));
+
+        let mut app = join_game_app(Duration::ZERO);
+        app.update();
+        app.update();
+        assert_eq!(client_status(&app), None);
+        assert_eq!(local_clients(&mut app), 0);
+        let notes = &app.world().resource::<NotificationQueue>().messages;
+        assert!(notes
+            .iter()
+            .any(|note| note.type_ == NotificationType::Error
+                && note.message.contains("127.0.0.1:9")));
+    }
+
+    #[test]
+    fn syncing_waits_for_the_server_marker() {
+        use crate::status_management::{AppScope, SessionLifecycle};
+
+        let mut app = join_game_app(Duration::from_secs(60));
+        // Left over from another session, not part of this world.
+        app.world_mut().spawn(Replicated);
+        app.update();
+        // Stand in for the session coming up.
+        app.world_mut()
+            .trigger(SetClientStatus::Transition(ClientStatus::Connected));
+        app.update();
+        app.update();
+        // Replicon hasn't picked up a session yet.
+        assert_eq!(client_status(&app), Some(ClientStatus::Connected));
+        connect_session(&mut app);
+        assert_eq!(client_status(&app), Some(ClientStatus::Syncing));
+        assert_eq!(
+            *app.world().resource::<State<AppScope>>().get(),
+            AppScope::InGame
+        );
+
+        app.world_mut().spawn(Replicated);
+        for _ in 0..5 {
+            app.update();
+        }
+        assert_eq!(client_status(&app), Some(ClientStatus::Syncing));
+        assert_eq!(app.world().resource::<SyncProgress>().received, 1);
+        assert_eq!(
+            *app.world().resource::<State<SessionLifecycle>>().get(),
+            SessionLifecycle::Loading
+        );
+
+        // One of the announced entities was despawned before it reached us, the marker
+        // still ends the sync.
+        app.world_mut().spawn(Replicated);
+        app.world_mut().write_message(SyncComplete { entities: 3 });
+        app.update();
+        app.update();
+        assert_eq!(client_status(&app), Some(ClientStatus::Running));
+        assert!(!app.world().contains_resource::<SyncProgress>());
+
+        let mut app = join_game_app(Duration::from_secs(60));
+        app.insert_resource(SyncTimeout(Duration::ZERO));
+        app.world_mut()
+            .trigger(SetClientStatus::Transition(ClientStatus::Connected));
+        connect_session(&mut app);
+        app.update();
+        assert_eq!(client_status(&app), Some(ClientStatus::Disconnecting));
+        for _ in 0..4 {
+            app.update();
+        }
+        // Given up and back in the menu.
+        assert_eq!(client_status(&app), None);
+        assert_eq!(
+            *app.world().resource::<State<AppScope>>().get(),
+            AppScope::Menu
+        );
+        let notes = &app.world().resource::<NotificationQueue>().messages;
+        assert!(notes
+            .iter()
+            .any(|note| note.type_ == NotificationType::Error
+                && note.message.contains("did not send the world")));
+    }
+
+    /// Stands in for a WebTransport session, replicon only looks for the [`Session`].
+    fn connect_session(app: &mut App) -> Entity {
+        let session = app
+            .world_mut()
+            .spawn((AeronetRepliconClient, Session::new(Instant::now(), 1200)))
+            .id();
+        app.update();
+        app.update();
+        session
+    }
+
+    fn player_names(app: &mut App) -> Vec<String> {
+        let mut names: Vec<_> = app
+            .world_mut()
+            .query::<&Player>()
+            .iter(app.world())
+            .map(|player| player.name.clone())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn leaving_a_server_despawns_what_it_replicated() {
+        let mut app = crate::test_app();
+        let server_players = ["Ada", "Grace"].map(|name| Player {
+            name: name.to_string(),
+        });
+        let server_names = ["Ada", "Grace"].map(String::from).to_vec();
+
+        // Join, leave and join again, the server sends its players every time.
+        for _ in 0..2 {
+            let session = connect_session(&mut app);
+            assert_eq!(
+                *app.world().resource::<State<ClientState>>().get(),
+                ClientState::Connected
+            );
+            for player in &server_players {
+                app.world_mut().spawn(player.clone());
+            }
+            app.update();
+            assert_eq!(player_names(&mut app), server_names);
+
+            app.world_mut().despawn(session);
+            app.update();
+            app.update();
+            assert!(player_names(&mut app).is_empty());
+        }
+    }
+
+    #[test]
+    fn reconnecting_keeps_one_player_per_server_player() {
+        let mut app = join_game_app(Duration::from_secs(60));
+        let server_players = ["Ada", "Grace"].map(|name| Player {
+            name: name.to_string(),
+        });
+        let server_names = ["Ada", "Grace"].map(String::from).to_vec();
+
+        let session = connect_session(&mut app);
+        for player in &server_players {
+            app.world_mut().spawn(player.clone());
+        }
+        app.update();
+        assert_eq!(player_names(&mut app), server_names);
+
+        // The connection drops while playing.
+        app.world_mut()
+            .trigger(SetClientStatus::Transition(ClientStatus::Reconnecting));
+        app.world_mut().despawn(session);
+        app.update();
+        app.update();
+        assert_eq!(client_status(&app), Some(ClientStatus::Reconnecting));
+        assert!(player_names(&mut app).is_empty());
+
+        // The new session gets the server's snapshot again.
+        connect_session(&mut app);
+        for player in &server_players {
+            app.world_mut().spawn(player.clone());
+        }
+        app.update();
+        assert_eq!(player_names(&mut app), server_names);
     }
 }
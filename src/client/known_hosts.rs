@@ -0,0 +1,397 @@
+use {
+    super::ClientTarget,
+    crate::{notifications::Notify, storage},
+    aeronet::io::Session,
+    aeronet_webtransport::{
+        cert,
+        wtransport::tls::{
+            client::NoServerVerification,
+            rustls::{
+                self,
+                client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+                pki_types::{CertificateDer, ServerName, UnixTime},
+                DigitallySignedStruct, SignatureScheme,
+            },
+            Certificate,
+        },
+    },
+    anyhow::Context,
+    bevy::prelude::*,
+    std::{
+        collections::BTreeMap,
+        path::{Path, PathBuf},
+        sync::{Arc, Mutex},
+    },
+};
+
+pub const KNOWN_SERVERS_FILE: &str = "known_servers";
+
+/// Loads the [`KnownHosts`] and remembers the key of every server we get a session with.
+pub struct KnownHostsPlugin;
+
+impl Plugin for KnownHostsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<KnownHosts>()
+            .add_systems(Startup, load_known_hosts)
+            .add_observer(on_session_remember_host)
+            .add_observer(on_trust_new_server_key);
+    }
+}
+
+/// SPKI fingerprints of the servers we connected to before, keyed by `host:port`.
+///
+/// Like SSH's `known_hosts`, a server is trusted the first time and then has to present
+/// the same key on every later connect. Hosts re-issue their short-lived certificate for
+/// the same key, so a renewal doesn't trip the check, only a new key does.
+#[derive(Resource, Debug, Clone, Default, PartialEq, Eq)]
+pub struct KnownHosts(pub BTreeMap<String, String>);
+
+impl KnownHosts {
+    pub fn default_path() -> PathBuf {
+        storage::data_dir().join(KNOWN_SERVERS_FILE)
+    }
+
+    /// One `address fingerprint` pair per line, so players can remove a server by hand.
+    /// `#` starts a comment.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => {
+                return Err(err).with_context(|| format!("reading {}", path.display()));
+            }
+        };
+
+        let mut hosts = BTreeMap::new();
+        for line in content.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            let mut fields = line.split_whitespace();
+            let (Some(address), Some(hash)) = (fields.next(), fields.next()) else {
+                continue;
+            };
+            if cert::hash_from_b64(hash).is_err() {
+                warn!(
+                    "Ignoring damaged key fingerprint of {address} in {}",
+                    path.display()
+                );
+                continue;
+            }
+            hosts.insert(address.to_string(), hash.to_string());
+        }
+        Ok(Self(hosts))
+    }
+
+    pub fn store(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+        }
+
+        let mut content = String::new();
+        for (address, hash) in &self.0 {
+            content.push_str(&format!("{address} {hash}\n"));
+        }
+
+        let tmp_path = PathBuf::from(format!("{}.tmp", path.display()));
+        std::fs::write(&tmp_path, content)
+            .with_context(|| format!("writing {}", tmp_path.display()))?;
+        std::fs::rename(&tmp_path, path).with_context(|| format!("replacing {}", path.display()))
+    }
+
+    pub fn get(&self, address: &str) -> Option<&str> {
+        self.0.get(address).map(String::as_str)
+    }
+
+    /// Remembers the fingerprint `hash` for `address`, replacing a previous one. False if nothing changed.
+    pub fn trust(&mut self, address: &str, hash: &str) -> bool {
+        self.0
+            .insert(address.to_string(), hash.to_string())
+            .as_deref()
+            != Some(hash)
+    }
+
+    fn persist(&self) {
+        if let Err(err) = self.store(&Self::default_path()) {
+            error!("Failed to persist {KNOWN_SERVERS_FILE}: {err:#}");
+        }
+    }
+}
+
+/// Key of `target` in the [`KnownHosts`], the host as typed in with its port.
+pub fn host_key(target: &ClientTarget) -> String {
+    if target.host.contains(':') {
+        format!("[{}]:{}", target.host, target.port)
+    } else {
+        format!("{}:{}", target.host, target.port)
+    }
+}
+
+/// Base64 SPKI fingerprint of the key a server presented, filled in during the TLS handshake.
+#[derive(Debug, Clone, Default)]
+pub struct PresentedCertificate(Arc<Mutex<Option<String>>>);
+
+impl PresentedCertificate {
+    pub fn get(&self) -> Option<String> {
+        self.0.lock().ok()?.clone()
+    }
+
+    fn set(&self, hash: String) {
+        if let Ok(mut presented) = self.0.lock() {
+            *presented = Some(hash);
+        }
+    }
+}
+
+/// Checks the server's key against a pinned SPKI fingerprint, or accepts any key from a
+/// server we don't know yet. Either way the presented fingerprint is recorded.
+///
+/// Pinning the key instead of the certificate keeps hosts trusted when they re-issue their
+/// short-lived certificate. The handshake signature proves the server holds that key.
+#[derive(Debug)]
+pub struct RecordingVerifier {
+    pinned: Option<String>,
+    presented: PresentedCertificate,
+    signatures: NoServerVerification,
+}
+
+impl RecordingVerifier {
+    pub fn new(pinned: Option<String>, presented: PresentedCertificate) -> Self {
+        Self {
+            pinned,
+            presented,
+            signatures: NoServerVerification::new(),
+        }
+    }
+}
+
+impl ServerCertVerifier for RecordingVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let certificate = Certificate::from_der(end_entity.to_vec())
+            .map_err(|_| rustls::CertificateError::BadEncoding)?;
+        let fingerprint = cert::spki_fingerprint_b64(&certificate)
+            .ok_or(rustls::CertificateError::BadEncoding)?;
+        self.presented.set(fingerprint.clone());
+
+        match &self.pinned {
+            Some(pinned) if *pinned != fingerprint => {
+                Err(rustls::CertificateError::ApplicationVerificationFailure.into())
+            }
+            _ => Ok(ServerCertVerified::assertion()),
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.signatures.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.signatures.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.signatures.supported_verify_schemes()
+    }
+}
+
+/// What a connection attempt expects from the server, kept on the [`crate::local::LocalClient`].
+#[derive(Component, Debug, Clone)]
+pub struct HostFingerprint {
+    pub address: String,
+    /// The key fingerprint the server is pinned to, `None` when trusting on first use.
+    pub expected: Option<String>,
+    pub presented: PresentedCertificate,
+}
+
+impl HostFingerprint {
+    /// The server presented another key than the one we pinned.
+    pub fn mismatch(&self) -> Option<FingerprintMismatch> {
+        let expected = self.expected.as_ref()?;
+        let presented = self.presented.get()?;
+        (presented != *expected).then(|| FingerprintMismatch {
+            address: self.address.clone(),
+            expected: expected.clone(),
+            presented,
+        })
+    }
+}
+
+/// The last connection was refused because the server's key changed, waiting for
+/// the player to either trust the new key with [`TrustNewServerKey`] or leave it.
+#[derive(Resource, Debug, Clone, PartialEq, Eq)]
+pub struct FingerprintMismatch {
+    pub address: String,
+    pub expected: String,
+    pub presented: String,
+}
+
+impl FingerprintMismatch {
+    pub fn describe(&self) -> String {
+        format!(
+            "The key of {} changed since the last connect, someone could be \
+             impersonating the server. Trust the new key only if the host confirms it changed.",
+            self.address
+        )
+    }
+}
+
+/// Replaces the known key of the [`FingerprintMismatch`] server with the one it presented.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct TrustNewServerKey;
+
+fn load_known_hosts(mut commands: Commands) {
+    match KnownHosts::load(&KnownHosts::default_path()) {
+        Ok(known_hosts) => commands.insert_resource(known_hosts),
+        // Trust on first use still works, we just start without remembered servers.
+        Err(err) => error!("Failed to load known servers: {err:#}"),
+    }
+}
+
+fn on_session_remember_host(
+    trigger: On<Add, Session>,
+    fingerprints: Query<&HostFingerprint>,
+    mut known_hosts: ResMut<KnownHosts>,
+    mut commands: Commands,
+) {
+    let Ok(fingerprint) = fingerprints.get(trigger.event_target()) else {
+        return;
+    };
+    if known_hosts.get(&fingerprint.address).is_some() {
+        return;
+    }
+    let Some(presented) = fingerprint.presented.get() else {
+        return;
+    };
+
+    info!("Remembering key {presented} of {}", fingerprint.address);
+    known_hosts.trust(&fingerprint.address, &presented);
+    known_hosts.persist();
+    commands.remove_resource::<FingerprintMismatch>();
+}
+
+fn on_trust_new_server_key(
+    _trigger: On<TrustNewServerKey>,
+    mismatch: Option<Res<FingerprintMismatch>>,
+    mut known_hosts: ResMut<KnownHosts>,
+    mut commands: Commands,
+) {
+    let Some(mismatch) = mismatch else {
+        return;
+    };
+
+    warn!(
+        "Trusting new key {} of {}, was {}",
+        mismatch.presented, mismatch.address, mismatch.expected
+    );
+    known_hosts.trust(&mismatch.address, &mismatch.presented);
+    known_hosts.persist();
+    commands.trigger(Notify::success(format!(
+        "Trusted the new key of {}, you can join again",
+        mismatch.address
+    )));
+    commands.remove_resource::<FingerprintMismatch>();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_hosts_round_trip_and_detect_changed_keys() {
+        let old_hash = "r/lhrD9QqJdT/5Cq5eJyA8UMRxYiozquZ2npB0D6y5U=";
+        let new_hash = "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=";
+        let path = storage::data_dir().join("known_servers_round_trip");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(
+            &path,
+            format!("# servers\nlan.host:25571 {old_hash}\nbroken.host:1 not-a-hash\n\n"),
+        )
+        .unwrap();
+
+        let mut known_hosts = KnownHosts::load(&path).unwrap();
+        assert_eq!(known_hosts.0.len(), 1);
+        assert_eq!(known_hosts.get("lan.host:25571"), Some(old_hash));
+        assert!(!known_hosts.trust("lan.host:25571", old_hash));
+        assert!(known_hosts.trust("[fe80::1]:25571", new_hash));
+        known_hosts.store(&path).unwrap();
+        assert_eq!(KnownHosts::load(&path).unwrap(), known_hosts);
+
+        let mut target = ClientTarget::default();
+        target.update_input("[fe80::1]".to_string());
+        assert_eq!(host_key(&target), "[fe80::1]:25571");
+
+        let fingerprint = HostFingerprint {
+            address: "lan.host:25571".to_string(),
+            expected: known_hosts.get("lan.host:25571").map(str::to_string),
+            presented: PresentedCertificate::default(),
+        };
+        // Nothing presented yet, e.g. the server was unreachable.
+        assert_eq!(fingerprint.mismatch(), None);
+        fingerprint.presented.set(old_hash.to_string());
+        assert_eq!(fingerprint.mismatch(), None);
+        fingerprint.presented.set(new_hash.to_string());
+        let mismatch = fingerprint.mismatch().unwrap();
+        assert_eq!(mismatch.expected, old_hash);
+        assert_eq!(mismatch.presented, new_hash);
+    }
+
+    fn self_signed(key_pair: &rcgen::KeyPair) -> CertificateDer<'static> {
+        rcgen::CertificateParams::new(vec!["localsingleplayer".to_string()])
+            .unwrap()
+            .self_signed(key_pair)
+            .unwrap()
+            .der()
+            .clone()
+    }
+
+    fn verify(verifier: &RecordingVerifier, certificate: &CertificateDer<'_>) -> bool {
+        verifier
+            .verify_server_cert(
+                certificate,
+                &[],
+                &ServerName::try_from("localsingleplayer").unwrap(),
+                &[],
+                UnixTime::now(),
+            )
+            .is_ok()
+    }
+
+    #[test]
+    fn renewed_certificate_with_the_same_key_is_still_accepted() {
+        let key_pair = rcgen::KeyPair::generate().unwrap();
+        let original = self_signed(&key_pair);
+        let renewed = self_signed(&key_pair);
+        assert_ne!(original, renewed);
+
+        let pinned =
+            cert::spki_fingerprint_b64(&Certificate::from_der(original.to_vec()).unwrap()).unwrap();
+        let fingerprint = HostFingerprint {
+            address: "lan.host:25571".to_string(),
+            expected: Some(pinned.clone()),
+            presented: PresentedCertificate::default(),
+        };
+        let verifier = RecordingVerifier::new(Some(pinned), fingerprint.presented.clone());
+
+        assert!(verify(&verifier, &renewed));
+        assert_eq!(fingerprint.mismatch(), None);
+
+        let other_key = self_signed(&rcgen::KeyPair::generate().unwrap());
+        assert!(!verify(&verifier, &other_key));
+        assert!(fingerprint.mismatch().is_some());
+    }
+}
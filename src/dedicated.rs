@@ -0,0 +1,355 @@
+use {
+    crate::{
+        protocol::join_link,
+        server::{
+            admission::AdmissionPolicy,
+            helpers,
+            rcon::{RconConfig, RCON_PORT},
+            ActiveServerPorts, ServerIdentity, ServerInfo, ServerNetworkConfig,
+        },
+        status_management::{
+            ServerVisibility, SessionType, SetSingleplayerStatus, SingleplayerStatus,
+        },
+    },
+    anyhow::{bail, Context},
+    bevy::{app::AppExit, prelude::*},
+    std::{
+        net::{Ipv4Addr, SocketAddr},
+        path::{Path, PathBuf},
+    },
+};
+
+/// Read from the working directory when no `--config` is given and the file exists.
+pub const DEFAULT_CONFIG_FILE: &str = "dedicated.cfg";
+
+pub const USAGE: &str = "\
+Usage: dedicated [--config <file>] [--<setting> <value>]...
+
+Settings, also accepted as `setting = value` lines in the config file:
+  name            Server name shown in the LAN browser
+  world           World name shown in the LAN browser, worlds are not saved yet
+  game-port       UDP port for game traffic
+  discovery-port  UDP port for LAN discovery
+  data-dir        Directory for the server identity, ban and allow lists
+  password        Password players need to join, empty for none
+  max-players     Player cap, 0 for unlimited
+  allow-list      Only admit players on the allow list (true/false)
+  rcon            Accept remote admin connections (true/false, default false)
+  rcon-address    Address for remote admin connections, default 127.0.0.1:25575
+  rcon-password   Password remote admins authenticate with, required for rcon
+
+Worlds are not persisted yet: every start begins a fresh world and nothing of it
+survives a stop. Only the server identity, ban and allow lists are kept in data-dir.";
+
+/// Runs the host session without a local client or UI and opens it to the network right away.
+///
+/// Expects a [`DedicatedServerConfig`] resource, the session is reused from singleplayer,
+/// which makes the server authoritative just like a hosted game.
+pub struct DedicatedServerPlugin;
+
+impl Plugin for DedicatedServerPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DedicatedServer>()
+            .add_systems(Startup, start_dedicated_session)
+            .add_systems(
+                OnEnter(SingleplayerStatus::Starting),
+                on_dedicated_session_starting,
+            )
+            .add_systems(
+                OnEnter(ServerVisibility::Public),
+                on_dedicated_server_public,
+            )
+            .add_systems(
+                OnEnter(ServerVisibility::Failed),
+                on_dedicated_server_failed,
+            )
+            .add_systems(
+                OnExit(SessionType::Singleplayer),
+                on_dedicated_session_ended,
+            );
+    }
+}
+
+/// Present while the app runs as a dedicated server.
+#[derive(Resource, Debug, Default)]
+pub struct DedicatedServer;
+
+/// Settings of a dedicated server, see [`USAGE`].
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct DedicatedServerConfig {
+    pub server_name: String,
+    pub world_name: String,
+    pub game_port: u16,
+    pub discovery_port: u16,
+    pub data_dir: Option<PathBuf>,
+    pub password: Option<String>,
+    pub max_players: u32,
+    pub allow_list: bool,
+    pub rcon: bool,
+    pub rcon_address: SocketAddr,
+    pub rcon_password: Option<String>,
+}
+
+impl Default for DedicatedServerConfig {
+    fn default() -> Self {
+        let info = ServerInfo::default();
+        Self {
+            server_name: info.name,
+            world_name: info.world_name,
+            game_port: helpers::GAME_PORT,
+            discovery_port: helpers::DISCOVERY_PORT,
+            data_dir: None,
+            password: None,
+            max_players: 0,
+            allow_list: false,
+            rcon: false,
+            rcon_address: SocketAddr::from((Ipv4Addr::LOCALHOST, RCON_PORT)),
+            rcon_password: None,
+        }
+    }
+}
+
+impl DedicatedServerConfig {
+    /// Reads the config file, then applies the flags on top of it.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> anyhow::Result<Self> {
+        let mut config_path = None;
+        let mut overrides = Vec::new();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let Some(flag) = arg.strip_prefix("--") else {
+                bail!("unexpected argument {arg:?}");
+            };
+            let (key, value) = match flag.split_once('=') {
+                Some((key, value)) => (key.to_string(), value.to_string()),
+                None => (
+                    flag.to_string(),
+                    args.next()
+                        .with_context(|| format!("--{flag} needs a value"))?,
+                ),
+            };
+            if key == "config" {
+                config_path = Some(PathBuf::from(value));
+            } else {
+                overrides.push((key, value));
+            }
+        }
+
+        let mut config = Self::default();
+        match config_path {
+            Some(path) => config.apply_file(&path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                config.apply_file(Path::new(DEFAULT_CONFIG_FILE))?
+            }
+            None => {}
+        }
+        for (key, value) in overrides {
+            config
+                .set(&key, &value)
+                .with_context(|| format!("--{key}"))?;
+        }
+        Ok(config)
+    }
+
+    /// `setting = value` per line, `#` starts a comment line.
+    pub fn apply_file(&mut self, path: &Path) -> anyhow::Result<()> {
+        let content =
+            std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let location = || format!("{}:{}", path.display(), index + 1);
+            let (key, value) = line
+                .split_once('=')
+                .with_context(|| format!("{}: expected `setting = value`", location()))?;
+            self.set(key.trim(), value.trim()).with_context(location)?;
+        }
+        Ok(())
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> anyhow::Result<()> {
+        match key {
+            "name" => self.server_name = value.to_string(),
+            "world" => self.world_name = value.to_string(),
+            "game-port" => {
+                self.game_port = helpers::ports::validate_port_range(parse(key, value)?)?
+            }
+            "discovery-port" => {
+                self.discovery_port = helpers::ports::validate_port_range(parse(key, value)?)?
+            }
+            "data-dir" => self.data_dir = Some(PathBuf::from(value)),
+            "password" => self.password = (!value.is_empty()).then(|| value.to_string()),
+            "max-players" => self.max_players = parse(key, value)?,
+            "allow-list" => self.allow_list = parse(key, value)?,
+            "rcon" => self.rcon = parse(key, value)?,
+            "rcon-address" => self.rcon_address = parse(key, value)?,
+            "rcon-password" => self.rcon_password = (!value.is_empty()).then(|| value.to_string()),
+            _ => bail!("unknown setting {key:?}"),
+        }
+        Ok(())
+    }
+}
+
+fn parse<T: std::str::FromStr>(key: &str, value: &str) -> anyhow::Result<T>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    value
+        .parse()
+        .with_context(|| format!("invalid value {value:?} for {key}"))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn start_dedicated_session(
+    config: Res<DedicatedServerConfig>,
+    mut server_info: ResMut<ServerInfo>,
+    mut network_config: ResMut<ServerNetworkConfig>,
+    mut policy: ResMut<AdmissionPolicy>,
+    mut rcon: ResMut<RconConfig>,
+    mut next_session_type: ResMut<NextState<SessionType>>,
+    mut next_singleplayer_state: ResMut<NextState<SingleplayerStatus>>,
+    mut next_server_state: ResMut<NextState<ServerVisibility>>,
+) {
+    info!("Starting dedicated server {:?}", config.server_name);
+    server_info.name = config.server_name.clone();
+    server_info.world_name = config.world_name.clone();
+    // Unattended, so a taken port is an error rather than a surprise port nobody knows about.
+    *network_config = ServerNetworkConfig {
+        game_port: config.game_port,
+        discovery_port: config.discovery_port,
+        allow_port_fallback: false,
+    };
+    policy.password = config.password.clone();
+    policy.max_players = config.max_players;
+    policy.allow_list_enabled = config.allow_list;
+    *rcon = RconConfig {
+        enabled: config.rcon,
+        address: config.rcon_address,
+        password: config.rcon_password.clone(),
+    };
+
+    next_session_type.set(SessionType::Singleplayer);
+    next_singleplayer_state.set(SingleplayerStatus::Starting);
+    next_server_state.set(ServerVisibility::PendingPublic);
+}
+
+fn on_dedicated_session_starting(mut commands: Commands) {
+    // Nothing to wait for, there is no local client to connect.
+    commands.trigger(SetSingleplayerStatus {
+        transition: SingleplayerStatus::Running,
+    });
+}
+
+fn on_dedicated_server_public(
+    ports: Option<Res<ActiveServerPorts>>,
+    identity: Option<Res<ServerIdentity>>,
+) {
+    let Some(ports) = ports else {
+        return;
+    };
+    match ports.discovery_port {
+        Some(discovery_port) => info!(
+            "Dedicated server is public on game port {} and discovery port {discovery_port}",
+            ports.game_port
+        ),
+        None => info!(
+            "Dedicated server is public on game port {}, LAN discovery is off",
+            ports.game_port
+        ),
+    }
+    if let (Some(ip), Some(identity)) = (helpers::get_local_ip(), identity) {
+        let address = SocketAddr::new(ip, ports.game_port);
+        info!(
+            "Join link: {}",
+            join_link::encode(address, &identity.spki_fingerprint)
+        );
+    }
+}
+
+fn on_dedicated_server_failed(mut app_exit: MessageWriter<AppExit>) {
+    error!("Dedicated server could not go public, shutting down");
+    app_exit.write(AppExit::error());
+}
+
+fn on_dedicated_session_ended(mut app_exit: MessageWriter<AppExit>) {
+    info!("Dedicated server stopped");
+    app_exit.write(AppExit::Success);
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::local::LocalClient, aeronet_webtransport::server::WebTransportServer};
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn flags_override_the_config_file() {
+        let path = std::env::temp_dir().join("fos_server_test_dedicated.cfg");
+        std::fs::write(
+            &path,
+            "# LAN party\nname = Basement\ngame-port = 26000\npassword = secret\n",
+        )
+        .unwrap();
+
+        let config = DedicatedServerConfig::from_args(args(&[
+            "--config",
+            path.to_str().unwrap(),
+            "--game-port=26001",
+            "--max-players",
+            "8",
+            "--rcon",
+            "true",
+        ]))
+        .unwrap();
+
+        assert_eq!(config.server_name, "Basement");
+        assert_eq!(config.game_port, 26001);
+        assert_eq!(config.max_players, 8);
+        assert_eq!(config.password.as_deref(), Some("secret"));
+        assert!(config.rcon && config.rcon_address.ip().is_loopback());
+        assert!(DedicatedServerConfig::from_args(args(&["--colour", "red"])).is_err());
+        assert!(DedicatedServerConfig::from_args(args(&["--game-port"])).is_err());
+    }
+
+    #[test]
+    fn dedicated_server_goes_public_without_a_local_client() {
+        let mut app = crate::test_app_with(DedicatedServerPlugin);
+        app.insert_resource(DedicatedServerConfig {
+            game_port: helpers::ports::find_free_port().unwrap(),
+            discovery_port: helpers::ports::find_free_port().unwrap(),
+            password: Some("secret".to_string()),
+            ..default()
+        });
+
+        for _ in 0..10 {
+            app.update();
+        }
+
+        assert_eq!(
+            *app.world().resource::<State<ServerVisibility>>().get(),
+            ServerVisibility::Public
+        );
+        assert!(app
+            .world()
+            .resource::<AdmissionPolicy>()
+            .password_required());
+        let world = app.world_mut();
+        assert_eq!(
+            world
+                .query_filtered::<(), With<WebTransportServer>>()
+                .iter(world)
+                .count(),
+            1
+        );
+        assert_eq!(
+            world
+                .query_filtered::<(), With<LocalClient>>()
+                .iter(world)
+                .count(),
+            0
+        );
+    }
+}
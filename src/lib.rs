@@ -1,23 +1,36 @@
 pub mod chat;
+#[cfg(feature = "client")]
 pub mod client;
+#[cfg(feature = "server")]
+pub mod dedicated;
 pub mod notifications;
+pub mod player;
 pub mod protocol;
+#[cfg(feature = "server")]
 pub mod server;
+#[cfg(feature = "server")]
 pub mod singleplayer;
 pub mod status_management;
+pub mod storage;
 pub use notifications::*;
 pub mod local;
+pub mod network_stats;
 
+#[cfg(feature = "client")]
+use {aeronet_replicon::client::AeronetRepliconClientPlugin, client::ClientLogicPlugin};
+#[cfg(feature = "server")]
+use {
+    aeronet_replicon::server::AeronetRepliconServerPlugin, server::ServerLogicPlugin,
+    singleplayer::SingleplayerLogicPlugin,
+};
 use {
-    aeronet_replicon::{client::AeronetRepliconClientPlugin, server::AeronetRepliconServerPlugin},
     bevy::prelude::*,
     bevy_replicon::prelude::*,
     chat::ChatPlugin,
-    client::ClientLogicPlugin,
+    network_stats::NetworkStatsPlugin,
+    player::PlayerPlugin,
     protocol::ProtocolPlugin,
     serde::{Deserialize, Serialize},
-    server::ServerLogicPlugin,
-    singleplayer::SingleplayerLogicPlugin,
     status_management::StatusManagementPlugin,
 };
 
@@ -28,21 +41,44 @@ pub struct DummyEvent;
 
 impl Plugin for FOSServerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins((
-            RepliconPlugins,
-            AeronetRepliconServerPlugin,
-            AeronetRepliconClientPlugin,
-            ProtocolPlugin,
-            StatusManagementPlugin,
-            SingleplayerLogicPlugin,
-            ServerLogicPlugin,
-            ClientLogicPlugin,
-            ChatPlugin,
-        ))
-        .init_resource::<NotificationQueue>()
-        .add_observer(on_notify)
-        .add_systems(Update, notification_lifecycle);
+        app.add_plugins(RepliconPlugins);
+        #[cfg(feature = "server")]
+        app.add_plugins(AeronetRepliconServerPlugin);
+        #[cfg(feature = "client")]
+        app.add_plugins(AeronetRepliconClientPlugin);
+        app.add_plugins((ProtocolPlugin, StatusManagementPlugin));
+        #[cfg(feature = "server")]
+        app.add_plugins((SingleplayerLogicPlugin, ServerLogicPlugin));
+        #[cfg(feature = "client")]
+        app.add_plugins(ClientLogicPlugin);
+        app.add_plugins((ChatPlugin, PlayerPlugin, NetworkStatsPlugin))
+            .init_resource::<NotificationQueue>()
+            .add_observer(on_notify)
+            .add_systems(Update, notification_lifecycle);
     }
 }
 
+/// Headless app with the [`FOSServerPlugin`] and `plugins`, ready to `update` in tests.
+#[cfg(test)]
+pub(crate) fn test_app_with<M>(plugins: impl bevy::app::Plugins<M>) -> App {
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        bevy::input::InputPlugin,
+        bevy::state::app::StatesPlugin,
+        FOSServerPlugin,
+    ))
+    .add_plugins(plugins);
+    // Replicon sets up its message channels in `Plugin::finish`, which only `App::run` calls.
+    app.finish();
+    app.cleanup();
+    app
+}
+
+/// [`test_app_with`] without any extra plugins.
+#[cfg(test)]
+pub(crate) fn test_app() -> App {
+    test_app_with(())
+}
+
 // Component to mark local sessions for easy cleanup
@@ -0,0 +1,105 @@
+use {
+    aeronet::transport::sampling::{SessionStats, SessionStatsSample, SessionStatsSampling},
+    bevy::prelude::*,
+    core::time::Duration,
+};
+
+/// How often an idle connection sends a keep-alive, used by both server and client.
+pub const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(1);
+/// Silence after which a connection counts as lost, used by both server and client.
+pub const MAX_IDLE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Keeps a [`NetworkStats`] on every session entity, on the server for each connected client
+/// and on the client for its connection to the server.
+pub struct NetworkStatsPlugin;
+
+impl Plugin for NetworkStatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_observer(on_session_stats_added).add_systems(
+            Update,
+            update_network_stats.run_if(resource_exists::<SessionStatsSampling>),
+        );
+    }
+}
+
+/// Connection quality of a session, refreshed from the most recent transport sample.
+#[derive(Component, Debug, Clone, Copy, Default, PartialEq)]
+pub struct NetworkStats {
+    /// Round trip time as measured by the connection, or by message acks if it has none.
+    pub rtt: Duration,
+    pub bytes_recv_per_sec: f64,
+    pub bytes_sent_per_sec: f64,
+    /// Fraction of packets lost, between 0 and 1.
+    pub packet_loss: f64,
+    pub msgs_recv_per_sec: f64,
+    pub msgs_sent_per_sec: f64,
+}
+
+impl NetworkStats {
+    /// `sample_rate` is the number of samples per second, the sample holds per-sample deltas.
+    pub fn from_sample(sample: &SessionStatsSample, sample_rate: f64) -> Self {
+        let per_sec = |count: usize| count as f64 * sample_rate;
+        Self {
+            rtt: sample.packet_rtt.unwrap_or(sample.msg_rtt),
+            bytes_recv_per_sec: per_sec(sample.packets_delta.bytes_recv.0),
+            bytes_sent_per_sec: per_sec(sample.packets_delta.bytes_sent.0),
+            packet_loss: sample.loss,
+            msgs_recv_per_sec: per_sec(sample.msgs_delta.msgs_recv.0),
+            msgs_sent_per_sec: per_sec(sample.msgs_delta.msgs_sent.0),
+        }
+    }
+
+    /// True when the round trip eats a noticeable part of [`MAX_IDLE_TIMEOUT`].
+    pub fn is_degraded(&self) -> bool {
+        self.rtt > MAX_IDLE_TIMEOUT / 4 || self.packet_loss > 0.1
+    }
+}
+
+fn on_session_stats_added(trigger: On<Add, SessionStats>, mut commands: Commands) {
+    commands
+        .entity(trigger.event_target())
+        .insert(NetworkStats::default());
+}
+
+fn update_network_stats(
+    sampling: Res<SessionStatsSampling>,
+    mut sessions: Query<(&SessionStats, &mut NetworkStats)>,
+) {
+    let sample_rate = sampling.rate();
+    for (stats, mut network_stats) in &mut sessions {
+        if let Some(sample) = stats.last() {
+            network_stats.set_if_neq(NetworkStats::from_sample(sample, sample_rate));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, core::num::Saturating};
+
+    #[test]
+    fn sample_deltas_become_rates() {
+        let mut sample = SessionStatsSample {
+            msg_rtt: Duration::from_millis(40),
+            loss: 0.25,
+            ..default()
+        };
+        sample.packets_delta.bytes_recv = Saturating(300);
+        sample.packets_delta.bytes_sent = Saturating(100);
+        sample.msgs_delta.msgs_recv = Saturating(3);
+
+        let stats = NetworkStats::from_sample(&sample, 10.0);
+        assert_eq!(stats.rtt, Duration::from_millis(40));
+        assert_eq!(stats.bytes_recv_per_sec, 3000.0);
+        assert_eq!(stats.bytes_sent_per_sec, 1000.0);
+        assert_eq!(stats.msgs_recv_per_sec, 30.0);
+        assert_eq!(stats.msgs_sent_per_sec, 0.0);
+        assert!(stats.is_degraded());
+
+        sample.packet_rtt = Some(Duration::from_millis(12));
+        sample.loss = 0.0;
+        let stats = NetworkStats::from_sample(&sample, 10.0);
+        assert_eq!(stats.rtt, Duration::from_millis(12));
+        assert!(!stats.is_degraded());
+    }
+}
@@ -1,4 +1,5 @@
 use bevy::prelude::*;
+#[cfg(feature = "ui")]
 use bevy_egui::{egui, EguiContexts};
 
 /// The type/level of the notification, determining its styling and semantic meaning.
@@ -92,6 +93,7 @@ pub fn notification_lifecycle(time: Res<Time>, mut queue: ResMut<NotificationQue
 }
 
 /// System to visualize notifications using egui (Temporary UI).
+#[cfg(feature = "ui")]
 pub fn ui_notification_system(mut egui: EguiContexts, mut queue: ResMut<NotificationQueue>) {
     let Ok(ctx) = egui.ctx_mut() else {
         return;
@@ -0,0 +1,560 @@
+use {
+    crate::{
+        notifications::Notify,
+        protocol::handshake::{PLAYER_ID_HEADER, PLAYER_NAME_HEADER},
+        status_management::SingleplayerStatus,
+        storage,
+    },
+    aeronet::io::Session,
+    bevy::prelude::*,
+    bevy_replicon::prelude::*,
+    serde::{Deserialize, Serialize},
+    sha2::{Digest, Sha256},
+    std::{
+        collections::HashMap,
+        fs, io,
+        path::{Path, PathBuf},
+        time::Duration,
+    },
+    uuid::Uuid,
+};
+
+pub const PROFILE_FILE: &str = "player_profile.bin";
+pub const MAX_PLAYER_NAME_LENGTH: usize = 32;
+/// What [`PlayerProfile::id_for`] is keyed with on the host's own server.
+const LOCAL_SERVER: &str = "local";
+
+pub struct PlayerPlugin;
+
+impl Plugin for PlayerPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PlayerProfile>()
+            .init_resource::<ReconnectGracePeriod>()
+            .add_systems(Startup, load_player_profile)
+            .add_systems(Update, expire_parked_players)
+            .add_systems(OnExit(SingleplayerStatus::Stopping), despawn_all_players)
+            .add_observer(on_set_player_name)
+            .add_observer(spawn_player_for_session);
+    }
+}
+
+/// Who a connected client (or the host's own [`crate::local::LocalClient`]) plays as.
+///
+/// Attached to the client entity once the session request passed admission.
+#[derive(Component, Debug, Clone, PartialEq, Eq)]
+pub struct PlayerIdentity {
+    pub id: Uuid,
+    pub name: String,
+}
+
+impl PlayerIdentity {
+    /// Reads the identity a client sent along with its session request.
+    pub fn from_headers(headers: &HashMap<String, String>) -> Option<Self> {
+        let id = Uuid::parse_str(headers.get(PLAYER_ID_HEADER)?.trim()).ok()?;
+        if id.is_nil() {
+            return None;
+        }
+        let name = sanitize_player_name(headers.get(PLAYER_NAME_HEADER)?)?;
+        Some(Self { id, name })
+    }
+}
+
+impl From<&PlayerProfile> for PlayerIdentity {
+    fn from(profile: &PlayerProfile) -> Self {
+        Self {
+            id: profile.id_for(LOCAL_SERVER),
+            name: profile.name.clone(),
+        }
+    }
+}
+
+/// A player in the world, spawned by the server and replicated to every client.
+#[derive(Component, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[require(Replicated)]
+pub struct Player {
+    pub name: String,
+}
+
+/// The [`PlayerIdentity::id`] a [`Player`] belongs to, only known to the server.
+///
+/// Clients prove nothing but knowing their id, so it is never replicated: anyone who
+/// learns another player's id can join as them.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlayerId(pub Uuid);
+
+/// Server side link from a [`Player`] to the client entity currently controlling it.
+#[derive(Component, Debug)]
+#[relationship(relationship_target = ControlledPlayer)]
+pub struct PlayerOf(pub Entity);
+
+/// The [`Player`] a client controls, see [`PlayerOf`].
+#[derive(Component, Debug)]
+#[relationship_target(relationship = PlayerOf)]
+pub struct ControlledPlayer(Entity);
+
+impl ControlledPlayer {
+    pub fn get(&self) -> Entity {
+        self.0
+    }
+}
+
+/// A [`Player`] whose client lost its connection, kept in the world instead of despawned.
+///
+/// Rejoining with the same id within [`ReconnectGracePeriod`] takes it over again.
+#[derive(Component, Debug, Default)]
+pub struct ParkedPlayer {
+    pub parked_for: Duration,
+}
+
+/// How long a [`ParkedPlayer`] waits for its client to come back before it is removed.
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct ReconnectGracePeriod(pub Duration);
+
+impl Default for ReconnectGracePeriod {
+    fn default() -> Self {
+        Self(Duration::from_secs(60))
+    }
+}
+
+/// The local player's secret and display name.
+///
+/// The secret never leaves this machine. Each server gets its own player id derived from it,
+/// see [`Self::id_for`], which ties the player to their parked character and to that server's
+/// ban and allow lists. An id is as good as a password on the server it was made for, but an
+/// operator who learns it cannot join other servers as the player.
+#[derive(Resource, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PlayerProfile {
+    pub secret: Uuid,
+    pub name: String,
+}
+
+impl Default for PlayerProfile {
+    fn default() -> Self {
+        Self {
+            secret: Uuid::new_v4(),
+            name: "Player".to_string(),
+        }
+    }
+}
+
+impl PlayerProfile {
+    /// The player id to present to `server`, an HMAC-SHA256 of its `host:port` under the secret.
+    ///
+    /// Keyed by the address rather than the server key, which is not known yet the first time
+    /// we connect. The known hosts file pins the key per address, so the id still only ever
+    /// reaches the server that first got it.
+    pub fn id_for(&self, server: &str) -> Uuid {
+        let digest = hmac_sha256(self.secret.as_bytes(), server.as_bytes());
+        let mut bytes = [0; 16];
+        bytes.copy_from_slice(&digest[..16]);
+        uuid::Builder::from_custom_bytes(bytes).into_uuid()
+    }
+
+    pub fn default_path() -> PathBuf {
+        storage::data_dir().join(PROFILE_FILE)
+    }
+
+    /// Loads the profile stored at `path`, creating and storing a new one on first start.
+    pub fn load_or_create(path: &Path) -> anyhow::Result<Self> {
+        match fs::read(path) {
+            Ok(bytes) => match postcard::from_bytes::<Self>(&bytes) {
+                Ok(profile) => return Ok(profile),
+                Err(err) => warn!(
+                    "Discarding unreadable player profile at {}: {err:#}",
+                    path.display()
+                ),
+            },
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                info!("Creating new player profile at {}", path.display());
+            }
+            Err(err) => return Err(err.into()),
+        }
+
+        let profile = Self::default();
+        profile.store(path)?;
+        Ok(profile)
+    }
+
+    pub fn store(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let temporary_path = path.with_extension("tmp");
+        fs::write(&temporary_path, postcard::to_allocvec(self)?)?;
+        fs::rename(&temporary_path, path)?;
+        Ok(())
+    }
+}
+
+/// HMAC as in RFC 2104, `key` must fit into one SHA-256 block.
+fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    let mut inner_key = [0x36; 64];
+    let mut outer_key = [0x5c; 64];
+    for (index, byte) in key.iter().enumerate() {
+        inner_key[index] ^= byte;
+        outer_key[index] ^= byte;
+    }
+    let inner = Sha256::new()
+        .chain_update(inner_key)
+        .chain_update(message)
+        .finalize();
+    Sha256::new()
+        .chain_update(outer_key)
+        .chain_update(inner)
+        .finalize()
+        .into()
+}
+
+/// Renames the local player, takes effect the next time we join a server.
+#[derive(Event, Debug, Clone)]
+pub struct SetPlayerName {
+    pub name: String,
+}
+
+/// Trims `name` and checks it is something other players can read, `None` if not.
+pub fn sanitize_player_name(name: &str) -> Option<String> {
+    let name = name.trim();
+    let length = name.chars().count();
+    let readable = name
+        .chars()
+        .all(|c| c.is_alphanumeric() || matches!(c, ' ' | '_' | '-' | '.'));
+
+    (readable && (1..=MAX_PLAYER_NAME_LENGTH).contains(&length)).then(|| name.to_string())
+}
+
+/// Removes a player that left for good.
+pub fn despawn_player(commands: &mut Commands, player: Entity) {
+    if let Ok(mut player) = commands.get_entity(player) {
+        player.despawn();
+    }
+}
+
+/// Detaches a player from its client but keeps it, and its state, in the world.
+pub fn park_player(commands: &mut Commands, player: Entity) {
+    if let Ok(mut player) = commands.get_entity(player) {
+        player.remove::<PlayerOf>().insert(ParkedPlayer::default());
+    }
+}
+
+/// Every admitted client gets its [`Player`] once the session is up, including the host's
+/// own local client. Clients of a remote server carry no [`PlayerIdentity`] and get none.
+fn spawn_player_for_session(
+    trigger: On<Add, Session>,
+    mut commands: Commands,
+    identities: Query<&PlayerIdentity>,
+    parked_players: Query<(Entity, &PlayerId), With<ParkedPlayer>>,
+) {
+    let client = trigger.event_target();
+    let Ok(identity) = identities.get(client) else {
+        return;
+    };
+
+    if let Some((player, _)) = parked_players
+        .iter()
+        .find(|(_, parked)| parked.0 == identity.id)
+    {
+        commands
+            .entity(player)
+            .remove::<ParkedPlayer>()
+            .insert(PlayerOf(client));
+        info!("{} ({client}) reconnected to {player}", identity.name);
+        return;
+    }
+
+    let player = commands
+        .spawn((
+            Name::new(format!("Player {}", identity.name)),
+            Player {
+                name: identity.name.clone(),
+            },
+            PlayerId(identity.id),
+            PlayerOf(client),
+        ))
+        .id();
+    info!("Spawned {player} for {} ({client})", identity.name);
+}
+
+fn expire_parked_players(
+    mut commands: Commands,
+    time: Res<Time>,
+    grace_period: Res<ReconnectGracePeriod>,
+    mut parked_players: Query<(Entity, &Player, &mut ParkedPlayer)>,
+) {
+    for (entity, player, mut parked) in &mut parked_players {
+        parked.parked_for += time.delta();
+        if parked.parked_for > grace_period.0 {
+            info!("{} did not reconnect in time", player.name);
+            despawn_player(&mut commands, entity);
+        }
+    }
+}
+
+/// The world goes away with the singleplayer session, parked players included.
+fn despawn_all_players(mut commands: Commands, players: Query<Entity, With<Player>>) {
+    for player in &players {
+        commands.entity(player).despawn();
+    }
+}
+
+fn load_player_profile(mut commands: Commands) {
+    match PlayerProfile::load_or_create(&PlayerProfile::default_path()) {
+        Ok(profile) => {
+            info!("Playing as {}", profile.name);
+            commands.insert_resource(profile);
+        }
+        // Keep the in-memory profile, we just can't remember it for the next start.
+        Err(err) => error!("Failed to load player profile: {err:#}"),
+    }
+}
+
+fn on_set_player_name(
+    trigger: On<SetPlayerName>,
+    mut commands: Commands,
+    mut profile: ResMut<PlayerProfile>,
+) {
+    let Some(name) = sanitize_player_name(&trigger.name) else {
+        commands.trigger(Notify::warning(format!(
+            "Player names need 1 to {MAX_PLAYER_NAME_LENGTH} letters, digits, spaces, '_', '-' or '.'"
+        )));
+        return;
+    };
+
+    profile.name = name;
+    if let Err(err) = profile.store(&PlayerProfile::default_path()) {
+        commands.trigger(Notify::error(format!(
+            "Failed to save player profile: {err:#}"
+        )));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn player_names_are_trimmed_and_validated() {
+        assert_eq!(sanitize_player_name("  Ada  "), Some("Ada".to_string()));
+        assert_eq!(
+            sanitize_player_name("Jörg_2.0"),
+            Some("Jörg_2.0".to_string())
+        );
+        assert_eq!(sanitize_player_name("   "), None);
+        assert_eq!(sanitize_player_name("line\nbreak"), None);
+        assert_eq!(
+            sanitize_player_name(&"x".repeat(MAX_PLAYER_NAME_LENGTH + 1)),
+            None
+        );
+    }
+
+    #[test]
+    fn player_identity_is_read_from_headers() {
+        let id = Uuid::new_v4();
+        let headers = HashMap::from([
+            (PLAYER_ID_HEADER.to_string(), id.to_string()),
+            (PLAYER_NAME_HEADER.to_string(), " Ada ".to_string()),
+        ]);
+        assert_eq!(
+            PlayerIdentity::from_headers(&headers),
+            Some(PlayerIdentity {
+                id,
+                name: "Ada".to_string()
+            })
+        );
+
+        let nil_id = HashMap::from([
+            (PLAYER_ID_HEADER.to_string(), Uuid::nil().to_string()),
+            (PLAYER_NAME_HEADER.to_string(), "Ada".to_string()),
+        ]);
+        assert_eq!(PlayerIdentity::from_headers(&nil_id), None);
+        assert_eq!(PlayerIdentity::from_headers(&HashMap::new()), None);
+    }
+
+    fn player_test_app() -> App {
+        let mut app = App::new();
+        app.init_resource::<Time>()
+            .insert_resource(ReconnectGracePeriod(Duration::from_secs(10)))
+            .add_systems(Update, expire_parked_players)
+            .add_observer(spawn_player_for_session);
+        app
+    }
+
+    fn ada() -> PlayerIdentity {
+        PlayerIdentity {
+            id: Uuid::new_v4(),
+            name: "Ada".to_string(),
+        }
+    }
+
+    fn advance(app: &mut App, by: Duration) {
+        app.world_mut().resource_mut::<Time>().advance_by(by);
+        app.world_mut().run_schedule(Update);
+    }
+
+    fn connect(app: &mut App, identity: Option<PlayerIdentity>) -> Entity {
+        let mut client = app.world_mut().spawn_empty();
+        if let Some(identity) = identity {
+            client.insert(identity);
+        }
+        client
+            .insert(Session::new(std::time::Instant::now(), 1200))
+            .id()
+    }
+
+    #[test]
+    fn admitted_sessions_get_a_player_and_others_do_not() {
+        let mut app = player_test_app();
+        let identity = PlayerIdentity {
+            id: Uuid::new_v4(),
+            name: "Ada".to_string(),
+        };
+        let client = connect(&mut app, Some(identity.clone()));
+        connect(&mut app, None);
+
+        let player = app
+            .world()
+            .get::<ControlledPlayer>(client)
+            .expect("client should control a player")
+            .get();
+        assert_eq!(
+            app.world().get::<Player>(player),
+            Some(&Player {
+                name: identity.name,
+            })
+        );
+        assert_eq!(
+            app.world().get::<PlayerId>(player),
+            Some(&PlayerId(identity.id))
+        );
+        assert!(app.world().get::<Replicated>(player).is_some());
+        assert_eq!(
+            app.world_mut().query::<&Player>().iter(app.world()).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn replicated_players_do_not_reveal_the_player_id() {
+        let mut app = player_test_app();
+        let identity = ada();
+        let client = connect(&mut app, Some(identity.clone()));
+        let player = app.world().get::<ControlledPlayer>(client).unwrap().get();
+
+        let replicated = postcard::to_allocvec(app.world().get::<Player>(player).unwrap()).unwrap();
+        let id_text = identity.id.to_string();
+        assert!(!replicated
+            .windows(16)
+            .any(|window| window == identity.id.as_bytes()));
+        assert!(!replicated
+            .windows(id_text.len())
+            .any(|window| window == id_text.as_bytes()));
+    }
+
+    #[test]
+    fn parked_players_outlive_their_client() {
+        let mut app = player_test_app();
+        let client = connect(
+            &mut app,
+            Some(PlayerIdentity {
+                id: Uuid::new_v4(),
+                name: "Ada".to_string(),
+            }),
+        );
+        let player = app.world().get::<ControlledPlayer>(client).unwrap().get();
+
+        park_player(&mut app.world_mut().commands(), player);
+        app.world_mut().flush();
+        app.world_mut().despawn(client);
+
+        assert!(app.world().get::<ParkedPlayer>(player).is_some());
+        assert!(app.world().get::<PlayerOf>(player).is_none());
+
+        despawn_player(&mut app.world_mut().commands(), player);
+        app.world_mut().flush();
+        assert!(app.world().get_entity(player).is_err());
+    }
+
+    #[test]
+    fn reconnecting_within_the_grace_period_takes_over_the_parked_player() {
+        let mut app = player_test_app();
+        let identity = ada();
+        let client = connect(&mut app, Some(identity.clone()));
+        let player = app.world().get::<ControlledPlayer>(client).unwrap().get();
+
+        park_player(&mut app.world_mut().commands(), player);
+        app.world_mut().despawn(client);
+        advance(&mut app, Duration::from_secs(9));
+
+        let client = connect(&mut app, Some(identity));
+        assert_eq!(
+            app.world()
+                .get::<ControlledPlayer>(client)
+                .map(ControlledPlayer::get),
+            Some(player)
+        );
+        assert!(app.world().get::<ParkedPlayer>(player).is_none());
+        assert_eq!(
+            app.world_mut().query::<&Player>().iter(app.world()).len(),
+            1
+        );
+
+        // The grace period starts over with the next connection loss.
+        advance(&mut app, Duration::from_secs(5));
+        assert!(app.world().get_entity(player).is_ok());
+    }
+
+    #[test]
+    fn parked_players_are_removed_after_the_grace_period() {
+        let mut app = player_test_app();
+        let identity = ada();
+        let client = connect(&mut app, Some(identity.clone()));
+        let player = app.world().get::<ControlledPlayer>(client).unwrap().get();
+
+        park_player(&mut app.world_mut().commands(), player);
+        app.world_mut().despawn(client);
+        advance(&mut app, Duration::from_secs(6));
+        assert!(app.world().get_entity(player).is_ok());
+        advance(&mut app, Duration::from_secs(6));
+        assert!(app.world().get_entity(player).is_err());
+
+        let client = connect(&mut app, Some(identity));
+        let new_player = app.world().get::<ControlledPlayer>(client).unwrap().get();
+        assert_ne!(new_player, player);
+    }
+
+    #[test]
+    fn player_ids_are_derived_per_server() {
+        let profile = PlayerProfile::default();
+        let id = profile.id_for("example.com:7777");
+
+        assert_eq!(profile.id_for("example.com:7777"), id);
+        assert_ne!(profile.id_for("example.com:7778"), id);
+        assert_ne!(profile.id_for(LOCAL_SERVER), id);
+        assert_ne!(PlayerProfile::default().id_for("example.com:7777"), id);
+        assert_ne!(id, profile.secret);
+        assert!(!id.is_nil());
+    }
+
+    #[test]
+    fn hmac_matches_the_rfc_4231_test_vector() {
+        let mac = hmac_sha256(&[0x0b; 20], b"Hi There");
+        assert_eq!(
+            mac.iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect::<String>(),
+            "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7"
+        );
+    }
+
+    #[test]
+    fn player_profile_is_reused_across_loads() {
+        let path = storage::data_dir().join("player_profile_reuse.bin");
+        let _ = fs::remove_file(&path);
+
+        let first = PlayerProfile::load_or_create(&path).unwrap();
+        let second = PlayerProfile::load_or_create(&path).unwrap();
+        assert_eq!(first, second);
+
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -1,17 +1,28 @@
 use {
+    crate::player::Player,
     bevy::prelude::*,
     bevy_replicon::prelude::*,
+    core::time::Duration,
     serde::{Deserialize, Serialize},
 };
 
+/// Port a server listens on for game traffic unless told otherwise.
+pub const GAME_PORT: u16 = 25571;
+
 pub struct ProtocolPlugin;
 
 impl Plugin for ProtocolPlugin {
     fn build(&self, app: &mut App) {
         // Wir versuchen es ohne expliziten Pfad, falls es im Prelude ist,
         // oder nutzen u8 falls es eine ID ist (eher unwahrscheinlich)
+        app.world_mut()
+            .resource_mut::<ProtocolHasher>()
+            .add_custom(handshake::PROTOCOL_VERSION);
         app.add_client_message::<ClientChat>(Channel::Ordered)
-            .add_server_message::<ServerChat>(Channel::Ordered);
+            .add_server_message::<ServerChat>(Channel::Ordered)
+            .add_server_message::<ServerShutdown>(Channel::Ordered)
+            .add_server_message::<SyncComplete>(Channel::Ordered)
+            .replicate::<Player>();
     }
 }
 
@@ -27,3 +38,279 @@ pub struct ServerChat {
     pub sender: String,
     pub text: String,
 }
+
+/// Announces that the server goes down, clients get disconnected once `countdown` is over
+/// or right away without one.
+#[derive(Event, Message, Serialize, Deserialize, Debug, Clone)]
+pub struct ServerShutdown {
+    pub reason: String,
+    pub countdown: Option<Duration>,
+}
+
+impl ServerShutdown {
+    /// Text shown to players.
+    pub fn describe(&self) -> String {
+        match self.countdown {
+            Some(countdown) => format!(
+                "{} in {} seconds",
+                self.reason,
+                countdown.as_secs_f32().ceil() as u32
+            ),
+            None => self.reason.clone(),
+        }
+    }
+}
+
+/// Sent to a client once it is authorized for replication. Server messages are held back
+/// until the client applied the replication of the tick they were written on, so receiving
+/// this means the initial world arrived.
+#[derive(Event, Message, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyncComplete {
+    /// Replicated entities at the time, for showing the sync progress.
+    pub entities: usize,
+}
+
+/// Headers a client sends with its WebTransport session request.
+pub mod handshake {
+    use {bevy_replicon::prelude::ProtocolHash, std::collections::HashMap};
+
+    /// Bump whenever client and server stop understanding each other. It is also part of
+    /// the replicon [`ProtocolHash`], which covers the registered messages and components.
+    pub const PROTOCOL_VERSION: u32 = 1;
+
+    pub const PASSWORD_HEADER: &str = "fos-password";
+    pub const PLAYER_ID_HEADER: &str = "fos-player-id";
+    pub const PLAYER_NAME_HEADER: &str = "fos-player-name";
+    pub const PROTOCOL_VERSION_HEADER: &str = "fos-protocol-version";
+    pub const PROTOCOL_HASH_HEADER: &str = "fos-protocol-hash";
+    pub const GAME_VERSION_HEADER: &str = "fos-game-version";
+
+    /// Header value for a [`ProtocolHash`], which keeps its number private.
+    pub fn encode_protocol_hash(protocol: &ProtocolHash) -> String {
+        postcard::to_allocvec(protocol)
+            .unwrap_or_default()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect()
+    }
+
+    /// Whether the headers announce the same protocol as `protocol_hash`, see
+    /// [`encode_protocol_hash`]. Clients from before the handshake send neither header.
+    pub fn is_same_protocol(headers: &HashMap<String, String>, protocol_hash: &str) -> bool {
+        let version = headers
+            .get(PROTOCOL_VERSION_HEADER)
+            .and_then(|version| version.trim().parse::<u32>().ok());
+        version == Some(PROTOCOL_VERSION)
+            && headers.get(PROTOCOL_HASH_HEADER).map(String::as_str) == Some(protocol_hash)
+    }
+}
+
+/// LAN discovery over UDP broadcast, independent of the replicon channels above.
+pub mod discovery {
+    use {
+        serde::{Deserialize, Serialize},
+        std::net::{Ipv4Addr, Ipv6Addr},
+    };
+
+    /// Port servers answer discovery requests on.
+    pub const DISCOVERY_PORT: u16 = 30000;
+    /// Organisation-local group servers join, reaches hosts a broadcast leaves out.
+    pub const MULTICAST_V4: Ipv4Addr = Ipv4Addr::new(239, 255, 70, 83);
+    /// Link-local group servers join on every interface, IPv6 has no broadcast.
+    pub const MULTICAST_V6: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0x4653, 0x5344);
+    pub const PROTOCOL_VERSION: u16 = 2;
+
+    /// Plain probe of the first protocol version, still answered for older clients.
+    pub const REQUEST_MAGIC_V1: &[u8] = b"FORGE_DISCOVER_V1";
+    pub const RESPONSE_PREFIX_V1: &str = "FORGE_RESP_V1;";
+    pub const REQUEST_MAGIC_V2: &[u8] = b"FORGE_DISCOVER_V2";
+    pub const RESPONSE_MAGIC_V2: &[u8] = b"FORGE_RESP_V2";
+
+    /// Large enough for any response we send while staying below a typical MTU.
+    pub const MAX_PACKET_SIZE: usize = 1200;
+    /// Keeps a V2 response within [`MAX_PACKET_SIZE`] no matter what the host typed in.
+    pub const MAX_NAME_LENGTH: usize = 64;
+
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+    pub struct DiscoveryRequest {
+        pub protocol_version: u16,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+    pub struct DiscoveryResponse {
+        pub protocol_version: u16,
+        pub game_port: u16,
+        /// SPKI fingerprint of the host's key, stays the same when its certificate is renewed.
+        pub key_fingerprint: Option<String>,
+        pub server_name: String,
+        pub game_version: String,
+        pub current_players: u32,
+        /// Zero means the host does not limit the number of players.
+        pub max_players: u32,
+        pub password_required: bool,
+        pub world_name: String,
+    }
+
+    pub fn encode_request(request: &DiscoveryRequest) -> Result<Vec<u8>, postcard::Error> {
+        postcard::to_extend(request, REQUEST_MAGIC_V2.to_vec())
+    }
+
+    pub fn decode_request(bytes: &[u8]) -> Option<DiscoveryRequest> {
+        postcard::from_bytes(bytes.strip_prefix(REQUEST_MAGIC_V2)?).ok()
+    }
+
+    pub fn encode_response(response: &DiscoveryResponse) -> Result<Vec<u8>, postcard::Error> {
+        postcard::to_extend(response, RESPONSE_MAGIC_V2.to_vec())
+    }
+
+    /// Decodes a response of either protocol version.
+    ///
+    /// V1 responses only carry the game port, the remaining fields are left at their defaults.
+    pub fn decode_response(bytes: &[u8]) -> Option<DiscoveryResponse> {
+        if let Some(payload) = bytes.strip_prefix(RESPONSE_MAGIC_V2) {
+            return postcard::from_bytes(payload).ok();
+        }
+
+        let payload = std::str::from_utf8(bytes)
+            .ok()?
+            .strip_prefix(RESPONSE_PREFIX_V1)?;
+        Some(DiscoveryResponse {
+            protocol_version: 1,
+            game_port: payload.parse::<u16>().ok()?,
+            ..Default::default()
+        })
+    }
+
+    /// Formats a response the way V1 clients expect it. They parse everything after the
+    /// prefix as the port, so this must stay exactly `FORGE_RESP_V1;<port>`.
+    pub fn encode_response_v1(response: &DiscoveryResponse) -> String {
+        format!("{RESPONSE_PREFIX_V1}{}", response.game_port)
+    }
+
+    /// Truncates host-provided text to [`MAX_NAME_LENGTH`] characters.
+    pub fn truncate_name(name: &str) -> String {
+        name.chars().take(MAX_NAME_LENGTH).collect()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn response() -> DiscoveryResponse {
+            DiscoveryResponse {
+                protocol_version: PROTOCOL_VERSION,
+                game_port: 25571,
+                key_fingerprint: Some("aGFzaA==".to_string()),
+                server_name: "Tim's World".to_string(),
+                game_version: "0.1.0".to_string(),
+                current_players: 2,
+                max_players: 8,
+                password_required: true,
+                world_name: "Valley".to_string(),
+            }
+        }
+
+        #[test]
+        fn v2_request_round_trips() {
+            let request = DiscoveryRequest {
+                protocol_version: PROTOCOL_VERSION,
+            };
+            let bytes = encode_request(&request).unwrap();
+            assert_eq!(decode_request(&bytes), Some(request));
+            assert_eq!(decode_request(REQUEST_MAGIC_V1), None);
+        }
+
+        #[test]
+        fn v2_response_round_trips() {
+            let bytes = encode_response(&response()).unwrap();
+            assert!(bytes.len() <= MAX_PACKET_SIZE);
+            assert_eq!(decode_response(&bytes), Some(response()));
+        }
+
+        #[test]
+        fn v1_response_only_carries_the_port() {
+            let encoded = encode_response_v1(&response());
+            assert_eq!(encoded, "FORGE_RESP_V1;25571");
+            let decoded = decode_response(encoded.as_bytes()).unwrap();
+            assert_eq!(decoded.protocol_version, 1);
+            assert_eq!(decoded.game_port, 25571);
+            assert_eq!(decoded.key_fingerprint, None);
+            assert!(decoded.server_name.is_empty());
+        }
+
+        #[test]
+        fn garbage_is_not_a_response() {
+            assert_eq!(decode_response(b"FORGE_RESP_V1;not-a-port"), None);
+            assert_eq!(decode_response(b"FORGE_RESP_V2\xff\xff"), None);
+            assert_eq!(decode_response(b"hello"), None);
+        }
+    }
+}
+
+/// `fos://host:port#fingerprint` links a host hands out, the fingerprint pins its key.
+pub mod join_link {
+    use std::net::SocketAddr;
+
+    pub const SCHEME: &str = "fos://";
+
+    /// The SPKI `key_fingerprint` is standard base64 like everywhere else, the link carries it URL-safe
+    /// and without padding.
+    pub fn encode(address: SocketAddr, key_fingerprint: &str) -> String {
+        let hash: String = key_fingerprint
+            .trim_end_matches('=')
+            .chars()
+            .map(|char| match char {
+                '+' => '-',
+                '/' => '_',
+                char => char,
+            })
+            .collect();
+        format!("{SCHEME}{address}#{hash}")
+    }
+
+    /// Splits a link into its address and the key fingerprint in standard base64,
+    /// `None` if the input isn't a link at all.
+    pub fn decode(input: &str) -> Option<(&str, Option<String>)> {
+        let link = input.trim().strip_prefix(SCHEME)?;
+        let Some((address, hash)) = link.split_once('#').filter(|(_, hash)| !hash.is_empty())
+        else {
+            return Some((link.trim_end_matches('#'), None));
+        };
+        let mut hash: String = hash
+            .chars()
+            .map(|char| match char {
+                '-' => '+',
+                '_' => '/',
+                char => char,
+            })
+            .collect();
+        while !hash.len().is_multiple_of(4) {
+            hash.push('=');
+        }
+        Some((address, Some(hash)))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn links_round_trip_the_key_fingerprint() {
+            let hash = "r/lhrD9QqJdT/5Cq5eJyA8UMRxYiozquZ2npB0D6y5U=";
+            let link = encode("192.168.1.10:25571".parse().unwrap(), hash);
+            assert_eq!(
+                link,
+                "fos://192.168.1.10:25571#r_lhrD9QqJdT_5Cq5eJyA8UMRxYiozquZ2npB0D6y5U"
+            );
+            assert_eq!(
+                decode(&link),
+                Some(("192.168.1.10:25571", Some(hash.to_string())))
+            );
+
+            let link = encode("[2001:db8::1]:25571".parse().unwrap(), hash);
+            assert_eq!(decode(&link).unwrap().0, "[2001:db8::1]:25571");
+            assert_eq!(decode("fos://myhost.lan"), Some(("myhost.lan", None)));
+            assert_eq!(decode("myhost.lan:25571"), None);
+        }
+    }
+}
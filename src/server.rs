@@ -1,11 +1,16 @@
 use {
     crate::{
-        protocol::{ClientChat, ServerChat},
+        local::LocalClient,
+        network_stats::{KEEP_ALIVE_INTERVAL, MAX_IDLE_TIMEOUT},
+        notifications::Notify,
+        player::{self, ControlledPlayer, PlayerIdentity, PlayerProfile},
+        protocol::{handshake, ClientChat, ServerChat, ServerShutdown, SyncComplete},
         status_management::{ServerVisibility, SetServerVisibility, SingleplayerStatus},
     },
+    admission::{AdmissionPlugin, AdmissionPolicy},
     aeronet::io::{
         connection::{Disconnect, Disconnected},
-        server::{Close, Closed, Server, ServerEndpoint},
+        server::{Close, CloseReason, Closed, Server, ServerEndpoint},
     },
     aeronet_io::connection::DisconnectReason,
     aeronet_replicon::server::AeronetRepliconServer,
@@ -14,18 +19,36 @@ use {
         server::{
             SessionRequest, WebTransportServer, WebTransportServerClient, WebTransportServerPlugin,
         },
+        wtransport::Identity,
     },
     bevy::prelude::*,
     bevy_replicon::prelude::*,
+    console::AdminConsolePlugin,
     core::time::Duration,
     helpers::DiscoveryServerPlugin,
+    rate_limit::{RateLimitPlugin, RateLimitedMessages},
+    rcon::RconPlugin,
 };
 
+pub mod admission;
+pub mod console;
+pub mod rate_limit;
+pub mod rcon;
+
 pub struct ServerLogicPlugin;
 
 impl Plugin for ServerLogicPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins((WebTransportServerPlugin, DiscoveryServerPlugin))
+            .add_plugins((
+                AdmissionPlugin,
+                AdminConsolePlugin,
+                RateLimitPlugin,
+                RconPlugin,
+            ))
+            .init_resource::<ServerInfo>()
+            .init_resource::<ServerNetworkConfig>()
+            .init_resource::<ShutdownCountdown>()
             .add_systems(
                 Update,
                 server_pending_going_public.run_if(in_state(ServerVisibility::PendingPublic)),
@@ -43,11 +66,139 @@ impl Plugin for ServerLogicPlugin {
                 OnEnter(ServerVisibility::GoingPrivate),
                 on_server_going_private,
             )
+            .add_systems(
+                Update,
+                server_going_private
+                    .run_if(in_state(ServerVisibility::GoingPrivate).and(shutdown_countdown_over)),
+            )
+            .add_systems(
+                Update,
+                tick_pending_shutdown.run_if(resource_exists::<PendingShutdown>),
+            )
+            .add_systems(OnEnter(ServerVisibility::Failed), on_server_failed)
+            .add_observer(on_server_visibility_failed)
+            .add_observer(on_server_shutdown_notify_clients)
             .add_observer(on_server_session_request)
-            .add_observer(on_server_client_disconnected);
+            .add_observer(on_server_client_disconnected)
+            .add_observer(on_server_client_authorized)
+            .add_observer(on_rotate_server_identity);
+    }
+}
+
+/// The certificate and key the host presents to clients.
+///
+/// Reloaded from disk whenever the server goes public. Clients pin the
+/// `spki_fingerprint`, which stays the same across sessions and certificate renewals.
+#[derive(Resource)]
+pub struct ServerIdentity {
+    pub identity: Identity,
+    pub cert_hash: String,
+    pub spki_fingerprint: String,
+}
+
+impl ServerIdentity {
+    pub fn new(identity: Identity) -> Self {
+        let cert = &identity.certificate_chain().as_slice()[0];
+        let cert_hash = cert::hash_to_b64(cert.hash());
+        let spki_fingerprint = cert::spki_fingerprint_b64(cert).unwrap_or_default();
+        Self {
+            identity,
+            cert_hash,
+            spki_fingerprint,
+        }
+    }
+}
+
+/// What the host advertises about itself to players browsing the LAN.
+#[derive(Resource, Debug, Clone)]
+pub struct ServerInfo {
+    pub name: String,
+    pub world_name: String,
+}
+
+impl Default for ServerInfo {
+    fn default() -> Self {
+        Self {
+            name: "FOS Server".to_string(),
+            world_name: "World".to_string(),
+        }
+    }
+}
+
+/// Ports the server listens on once it goes public.
+#[derive(Resource, Debug, Clone)]
+pub struct ServerNetworkConfig {
+    pub game_port: u16,
+    pub discovery_port: u16,
+    /// Pick a free game port instead of failing when the preferred one is taken, and go
+    /// public without LAN discovery when the discovery port is. Clients only ever probe
+    /// the configured discovery port, so there is nothing to fall back to.
+    pub allow_port_fallback: bool,
+}
+
+impl Default for ServerNetworkConfig {
+    fn default() -> Self {
+        Self {
+            game_port: helpers::GAME_PORT,
+            discovery_port: helpers::DISCOVERY_PORT,
+            allow_port_fallback: true,
+        }
+    }
+}
+
+/// Ports the public server actually uses, which differ from [`ServerNetworkConfig`]
+/// after a fallback.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ActiveServerPorts {
+    pub game_port: u16,
+    /// `None` while LAN discovery is off because its port was taken.
+    pub discovery_port: Option<u16>,
+}
+
+/// Reports why the server could not be opened (or kept open) and rolls it back to
+/// [`ServerVisibility::Private`] via [`ServerVisibility::Failed`].
+#[derive(Event, Debug, Clone)]
+pub struct ServerVisibilityFailed {
+    pub reason: String,
+}
+
+/// How long remote players are warned before a shutdown disconnects them, zero skips the wait.
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct ShutdownCountdown(pub Duration);
+
+impl Default for ShutdownCountdown {
+    fn default() -> Self {
+        Self(Duration::from_secs(5))
     }
 }
 
+/// Warns the remote players that the server is about to go down, see [`ShutdownCountdown`].
+#[derive(Event, Debug, Clone)]
+pub struct NotifyServerShutdown {
+    pub reason: String,
+}
+
+/// A shutdown the remote players were warned about, they are disconnected once the timer
+/// is finished.
+#[derive(Resource, Debug)]
+pub struct PendingShutdown {
+    pub reason: String,
+    pub timer: Timer,
+}
+
+/// Run condition for disconnecting remote players, which may always go ahead without an
+/// announced shutdown.
+pub fn shutdown_countdown_over(pending_shutdown: Option<Res<PendingShutdown>>) -> bool {
+    pending_shutdown.is_none_or(|pending| pending.timer.is_finished())
+}
+
+/// Replaces the stored server key and certificate with freshly generated ones.
+///
+/// Takes effect the next time the server goes public; every client that pinned
+/// the old key fingerprint has to pick up the new one.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct RotateServerIdentity;
+
 pub fn server_pending_going_public(
     mut commands: Commands,
     singleplayer_state: Res<State<SingleplayerStatus>>,
@@ -62,33 +213,52 @@ pub fn server_pending_going_public(
     }
 }
 
-pub fn on_server_going_public(mut commands: Commands) {
-    // TODO: implement error if server cant get started
+pub fn on_server_going_public(mut commands: Commands, network_config: Res<ServerNetworkConfig>) {
     // TODO: Implement User interface infos for server
-    // TODO: Implement Port usage detection
-    let identity = aeronet_webtransport::wtransport::Identity::self_signed([
-        "localsingleplayer",
-        "127.0.0.1",
-        "::1",
-        // IPv6
-    ])
-    .expect("all given SANs should be valid DNS names");
-    let cert = &identity.certificate_chain().as_slice()[0];
-    let _spki_fingerprint =
-        cert::spki_fingerprint_b64(cert).expect("should be a valid certificate");
-    let _cert_hash = cert::hash_to_b64(cert.hash());
-    println!("************************");
-    println!("SPKI FINGERPRINT");
-    println!("  {_spki_fingerprint}");
-    println!("CERTIFICATE HASH");
-    println!("  {_cert_hash}");
-    println!("************************");
+    let ports = match helpers::ports::resolve_server_ports(&network_config) {
+        Ok(ports) => ports,
+        Err(err) => {
+            commands.trigger(ServerVisibilityFailed {
+                reason: helpers::describe_startup_error(&err),
+            });
+            return;
+        }
+    };
+    match ports.discovery_port {
+        Some(discovery_port) => info!(
+            "Server uses game port {} and discovery port {discovery_port}",
+            ports.game_port
+        ),
+        None => {
+            info!("Server uses game port {}", ports.game_port);
+            commands.trigger(Notify::warning(format!(
+                "Discovery port {} is in use, the server won't show up in LAN server lists. \
+                 Players can still join by address.",
+                network_config.discovery_port
+            )));
+        }
+    }
+    commands.insert_resource(ports);
+
+    let identity = match helpers::identity::load_or_generate(&helpers::identity::default_path()) {
+        Ok(identity) => identity,
+        Err(err) => {
+            commands.trigger(ServerVisibilityFailed {
+                reason: format!("could not load the server identity: {err:#}"),
+            });
+            return;
+        }
+    };
+    let server_identity = ServerIdentity::new(identity.clone_identity());
+    info!("SPKI fingerprint: {}", server_identity.spki_fingerprint);
+    info!("Certificate hash: {}", server_identity.cert_hash);
+    commands.insert_resource(server_identity);
 
     let config = aeronet_webtransport::wtransport::ServerConfig::builder()
-        .with_bind_default(25571)
+        .with_bind_default(ports.game_port)
         .with_identity(identity)
-        .keep_alive_interval(Some(Duration::from_secs(1)))
-        .max_idle_timeout(Some(Duration::from_secs(5)))
+        .keep_alive_interval(Some(KEEP_ALIVE_INTERVAL))
+        .max_idle_timeout(Some(MAX_IDLE_TIMEOUT))
         .expect("should be a valid idle timeout")
         .build();
 
@@ -100,19 +270,25 @@ pub fn on_server_going_public(mut commands: Commands) {
 }
 
 pub fn handle_client_chat(
-    mut client_chat_events: MessageReader<FromClient<ClientChat>>,
+    mut client_chat_events: RateLimitedMessages<ClientChat>,
     mut server_chat_events: MessageWriter<ToClients<ServerChat>>,
+    identities: Query<&PlayerIdentity>,
+    host_profile: Res<PlayerProfile>,
 ) {
     for FromClient {
         client_id, message, ..
     } in client_chat_events.read()
     {
-        info!("Chat from client {:?}: {}", client_id, message.text);
+        let sender = match client_id {
+            ClientId::Server => host_profile.name.clone(),
+            ClientId::Client(client) => helpers::player_name(*client, &identities),
+        };
+        info!("Chat from {sender}: {}", message.text);
 
         server_chat_events.write(ToClients {
             mode: SendMode::Broadcast,
             message: ServerChat {
-                sender: format!("Client {:?}", client_id),
+                sender,
                 text: message.text.clone(),
             },
         });
@@ -147,24 +323,39 @@ pub fn on_server_going_private(
     mut commands: Commands,
     client_query: Query<Entity, With<WebTransportServerClient>>,
     server_query: Query<Entity, With<WebTransportServer>>,
-    mut next_state: ResMut<NextState<ServerVisibility>>,
 ) {
     info!(
         "Server going down\n Still {} clients connected\n Servers: {} active",
         client_query.iter().count(),
         server_query.iter().count()
     );
+    commands.trigger(NotifyServerShutdown {
+        reason: "Server closing".to_string(),
+    });
+}
+
+pub fn server_going_private(
+    mut commands: Commands,
+    pending_shutdown: Option<Res<PendingShutdown>>,
+    client_query: Query<Entity, With<WebTransportServerClient>>,
+    server_query: Query<Entity, With<WebTransportServer>>,
+    mut next_state: ResMut<NextState<ServerVisibility>>,
+) {
     if !client_query.is_empty() {
         {
             info!("Disconnect all clients");
+            let reason = pending_shutdown
+                .as_deref()
+                .map_or("Server closing", |pending| &pending.reason);
             for client in client_query.iter() {
                 {
-                    commands.trigger(Disconnect::new(client, "Server closing"));
+                    commands.trigger(Disconnect::new(client, reason));
                 }
             }
             return;
         }
     }
+    commands.remove_resource::<PendingShutdown>();
     if let Ok(server) = server_query.single() {
         {
             info!("Close server");
@@ -194,19 +385,75 @@ pub fn check_is_server_private(
     }
 }
 
-pub fn on_check_is_server_private(_: On<Closed>, mut commands: Commands) {
+pub fn on_check_is_server_private(trigger: On<Closed>, mut commands: Commands) {
     info!("Closed is triggered");
+    match &trigger.reason {
+        CloseReason::ByUser(_) => {
+            commands.trigger(SetServerVisibility {
+                transition: ServerVisibility::Private,
+            });
+        }
+        // Typically the endpoint could not be created, e.g. the port got taken in the meantime.
+        CloseReason::ByError(err) => {
+            commands.trigger(ServerVisibilityFailed {
+                reason: helpers::describe_startup_error(err),
+            });
+        }
+    }
+}
+
+pub fn on_server_visibility_failed(trigger: On<ServerVisibilityFailed>, mut commands: Commands) {
+    commands.trigger(Notify::error(format!(
+        "Could not open the game to LAN: {}",
+        trigger.reason
+    )));
+    commands.trigger(SetServerVisibility {
+        transition: ServerVisibility::Failed,
+    });
+}
+
+/// Tears down whatever part of the server already came up, the local game keeps running.
+pub fn on_server_failed(
+    mut commands: Commands,
+    server_query: Query<Entity, With<WebTransportServer>>,
+) {
+    for server in &server_query {
+        commands.trigger(Close::new(server, "Server failed"));
+    }
+    commands.remove_resource::<ActiveServerPorts>();
     commands.trigger(SetServerVisibility {
         transition: ServerVisibility::Private,
     });
 }
 
-pub fn on_server_session_request(trigger: On<SessionRequest>, clients: Query<&ChildOf>) {
+#[allow(clippy::too_many_arguments)]
+pub fn on_server_session_request(
+    trigger: On<SessionRequest>,
+    mut commands: Commands,
+    clients: Query<&ChildOf>,
+    protocol: Res<ProtocolHash>,
+    policy: Res<AdmissionPolicy>,
+    remote_clients: Query<(), With<WebTransportServerClient>>,
+    local_clients: Query<(), With<LocalClient>>,
+    identities: Query<&PlayerIdentity>,
+) {
     let client = trigger.event_target();
     let Ok(&ChildOf(server)) = clients.get(client) else {
         return;
     };
 
+    // Rejected clients are accepted as well and get disconnected with the reason right away,
+    // see `RejectedSession` for why and what keeps them from seeing anything.
+    admission::admit_session(
+        &trigger,
+        &mut commands,
+        &handshake::encode_protocol_hash(&protocol),
+        &policy,
+        &remote_clients,
+        &local_clients,
+        &identities,
+    );
+
     // TODO: Implement one-session-per-IP check.
     // Currently SessionRequest in aeronet_webtransport (0.18) does not expose remote_address.
     // We need to find another way to validate the client IP or wait for an update.
@@ -214,56 +461,162 @@ pub fn on_server_session_request(trigger: On<SessionRequest>, clients: Query<&Ch
     helpers::handle_server_accept_connection(client, server, trigger);
 }
 
+/// Marks the end of the initial world for the client, see [`SyncComplete`].
+pub fn on_server_client_authorized(
+    trigger: On<Add, AuthorizedClient>,
+    replicated: Query<(), With<Replicated>>,
+    mut sync_messages: MessageWriter<ToClients<SyncComplete>>,
+) {
+    let entities = replicated.iter().count();
+    debug!(
+        "Client {} authorized, syncing {entities} entities",
+        trigger.event_target()
+    );
+    sync_messages.write(ToClients {
+        mode: SendMode::Direct(ClientId::Client(trigger.event_target())),
+        message: SyncComplete { entities },
+    });
+}
+
 pub fn on_server_client_disconnected(
     trigger: On<Disconnected>,
-    // Optional: Query for player data if needed
+    mut commands: Commands,
+    identities: Query<&PlayerIdentity>,
+    controlled_players: Query<&ControlledPlayer>,
 ) {
     let client_entity = trigger.event_target();
+    let name = helpers::player_name(client_entity, &identities);
+    // Rejected or still connecting clients never got a player.
+    let Ok(player) = controlled_players
+        .get(client_entity)
+        .map(ControlledPlayer::get)
+    else {
+        info!(
+            "{name} ({client_entity}) disconnected: {:?}",
+            trigger.reason
+        );
+        return;
+    };
 
     match &trigger.reason {
         DisconnectReason::ByPeer(reason) => {
-            on_server_client_graceful_disconnect(client_entity, reason);
+            on_server_client_graceful_disconnect(&mut commands, player, &name, reason);
         }
         DisconnectReason::ByError(err) => {
             let err_msg = err.to_string();
             // Simple heuristic to distinguish timeout from other errors
             if err_msg.to_lowercase().contains("timed out") {
-                on_server_client_timeout(client_entity, err_msg);
+                on_server_client_timeout(&mut commands, player, &name, err_msg);
             } else {
-                on_server_client_lost(client_entity, err_msg);
+                on_server_client_lost(&mut commands, player, &name, err_msg);
             }
         }
         DisconnectReason::ByUser(reason) => {
-            info!("Client {client_entity} was kicked by server: {reason}");
+            info!("{name} ({client_entity}) was kicked by server: {reason}");
+            player::despawn_player(&mut commands, player);
         }
     }
 }
 
-pub fn on_server_client_timeout(client: Entity, msg: String) {
-    warn!("Client {client} timed out: {msg}");
-    // TODO: Cleanup player entity, notify others, etc.
+pub fn on_server_client_timeout(commands: &mut Commands, player: Entity, name: &str, msg: String) {
+    warn!("{name} timed out: {msg}");
+    player::park_player(commands, player);
 }
 
-pub fn on_server_client_lost(client: Entity, msg: String) {
-    error!("Client {client} connection lost: {msg}");
-    // TODO: Handle sudden connection loss (e.g. keep player data for reconnect)
+pub fn on_server_client_lost(commands: &mut Commands, player: Entity, name: &str, msg: String) {
+    error!("{name} connection lost: {msg}");
+    player::park_player(commands, player);
 }
 
-pub fn on_server_client_graceful_disconnect(client: Entity, msg: &str) {
-    info!("Client {client} left the game gracefully: {msg}");
-    // TODO: Save player state, clean up entity immediately
+pub fn on_server_client_graceful_disconnect(
+    commands: &mut Commands,
+    player: Entity,
+    name: &str,
+    msg: &str,
+) {
+    info!("{name} left the game gracefully: {msg}");
+    // TODO: Save player state
+    player::despawn_player(commands, player);
 }
 
-pub fn on_server_shutdown_notify_clients() {
-    todo!("Implement on_server_shutdown_notify_clients")
+pub fn on_rotate_server_identity(_: On<RotateServerIdentity>, mut commands: Commands) {
+    match helpers::identity::rotate(&helpers::identity::default_path()) {
+        Ok(identity) => {
+            let server_identity = ServerIdentity::new(identity);
+            info!(
+                "Rotated server identity, new key fingerprint: {}",
+                server_identity.spki_fingerprint
+            );
+            commands.trigger(Notify::success(
+                "Server identity rotated, clients need the new key fingerprint",
+            ));
+            commands.insert_resource(server_identity);
+        }
+        Err(err) => {
+            commands.trigger(Notify::error(format!(
+                "Failed to rotate server identity: {err:#}"
+            )));
+        }
+    }
+}
+
+/// Broadcasts the shutdown to the remote players and starts the [`PendingShutdown`], without
+/// remote players there is nobody to wait for.
+pub fn on_server_shutdown_notify_clients(
+    trigger: On<NotifyServerShutdown>,
+    mut commands: Commands,
+    countdown: Res<ShutdownCountdown>,
+    pending_shutdown: Option<Res<PendingShutdown>>,
+    client_query: Query<(), With<WebTransportServerClient>>,
+    mut shutdown_messages: MessageWriter<ToClients<ServerShutdown>>,
+) {
+    if pending_shutdown.is_some() || client_query.is_empty() {
+        return;
+    }
+
+    let notice = ServerShutdown {
+        reason: trigger.reason.clone(),
+        countdown: (!countdown.0.is_zero()).then_some(countdown.0),
+    };
+    info!(
+        "Notifying {} clients: {}",
+        client_query.iter().count(),
+        notice.describe()
+    );
+    shutdown_messages.write(ToClients {
+        mode: SendMode::Broadcast,
+        message: notice,
+    });
+    commands.insert_resource(PendingShutdown {
+        reason: trigger.reason.clone(),
+        timer: Timer::new(countdown.0, TimerMode::Once),
+    });
+}
+
+pub fn tick_pending_shutdown(time: Res<Time>, mut pending_shutdown: ResMut<PendingShutdown>) {
+    pending_shutdown.timer.tick(time.delta());
 }
 
 pub mod helpers {
     use {
-        crate::status_management::ServerVisibility,
-        aeronet_webtransport::server::{SessionRequest, SessionResponse},
+        crate::{
+            local::LocalClient,
+            player::PlayerIdentity,
+            protocol::{
+                discovery::{
+                    self, DiscoveryResponse, MAX_PACKET_SIZE, MULTICAST_V4, MULTICAST_V6,
+                    PROTOCOL_VERSION, REQUEST_MAGIC_V1,
+                },
+                handshake::PASSWORD_HEADER,
+            },
+            status_management::ServerVisibility,
+        },
+        aeronet::io::Session,
+        aeronet_webtransport::server::{SessionRequest, SessionResponse, WebTransportServerClient},
         bevy::prelude::*,
-        std::net::UdpSocket,
+        if_addrs::{IfAddr, Interface},
+        socket2::{Domain, Protocol, Socket, Type},
+        std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
     };
 
     pub(super) fn handle_server_accept_connection(
@@ -273,29 +626,58 @@ pub mod helpers {
     ) {
         info!("{client} connecting to {server} with headers:");
         for (header_key, header_value) in &trigger.headers {
-            info!("  {header_key}: {header_value}");
+            if header_key == PASSWORD_HEADER {
+                info!("  {header_key}: <hidden>");
+            } else {
+                info!("  {header_key}: {header_value}");
+            }
         }
 
         trigger.respond(SessionResponse::Accepted);
     }
 
+    /// Display name of a client for logs and chat, falls back to the entity before the handshake.
+    pub fn player_name(client: Entity, identities: &Query<&PlayerIdentity>) -> String {
+        identities.get(client).map_or_else(
+            |_| format!("Client {client}"),
+            |identity| identity.name.clone(),
+        )
+    }
+
+    /// Turns a server startup error into something a player can act on.
+    pub fn describe_startup_error(err: &anyhow::Error) -> String {
+        let io_error_kind = err
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<std::io::Error>())
+            .map(std::io::Error::kind);
+
+        match io_error_kind {
+            Some(std::io::ErrorKind::AddrInUse) => format!("port already in use ({err:#})"),
+            Some(std::io::ErrorKind::PermissionDenied) => {
+                format!("permission denied ({err:#})")
+            }
+            _ => format!("{err:#}"),
+        }
+    }
+
     pub fn get_local_ip() -> Option<std::net::IpAddr> {
         let socket = std::net::UdpSocket::bind("0.0.0.0:0").ok()?;
         socket.connect("8.8.8.8:80").ok()?;
         socket.local_addr().ok().map(|addr| addr.ip())
     }
 
-    pub(super) fn _handle_server_reject_connection() {
-        // TODO: client UUID or Name is on the server's blacklist
-        // TODO: Server password is incorrect
-        // TODO: Server is full
-        todo!("Implement on_server_shutdown_notify_clients")
-    }
-
     pub mod ports {
-        use std::net::{TcpListener, UdpSocket};
+        use {
+            crate::server::{ActiveServerPorts, ServerNetworkConfig},
+            anyhow::{anyhow, bail},
+            bevy::prelude::*,
+            std::net::{TcpListener, UdpSocket},
+        };
+
+        /// Ports below this need elevated privileges on most systems.
+        pub const MIN_PORT: u16 = 1024;
 
-        pub(in crate::server) fn _is_server_port_available(port: u16) -> bool {
+        pub(in crate::server) fn is_server_port_available(port: u16) -> bool {
             // UDP (für Discovery oder QUIC-ähnliches)
             if UdpSocket::bind(("0.0.0.0", port)).is_err() {
                 return false;
@@ -308,25 +690,232 @@ pub mod helpers {
         }
 
         pub fn find_free_port() -> Option<u16> {
-            // OS gibt freien Port, wenn 0 gebunden wird
-            TcpListener::bind(("127.0.0.1", 0))
+            // OS gibt freien Port, wenn 0 gebunden wird. UDP, weil QUIC und Discovery UDP sprechen.
+            UdpSocket::bind(("0.0.0.0", 0))
                 .ok()
                 .and_then(|sock| sock.local_addr().ok())
                 .map(|addr| addr.port())
         }
 
-        pub fn validate_port_range() {
-            // TODO: Validate port range
-            todo!("Implement validate_port_range")
+        pub fn validate_port_range(port: u16) -> anyhow::Result<u16> {
+            if port < MIN_PORT {
+                bail!(
+                    "port {port} is outside the allowed range {MIN_PORT}-{}",
+                    u16::MAX
+                );
+            }
+            Ok(port)
+        }
+
+        /// Returns `preferred` if it is valid and free, otherwise a free port chosen by
+        /// the OS when `allow_fallback` is set.
+        pub fn resolve_port(preferred: u16, allow_fallback: bool) -> anyhow::Result<u16> {
+            validate_port_range(preferred)?;
+            if is_server_port_available(preferred) {
+                return Ok(preferred);
+            }
+            if !allow_fallback {
+                bail!("port {preferred} is already in use");
+            }
+            find_free_port()
+                .ok_or_else(|| anyhow!("port {preferred} is in use and no free port is left"))
+        }
+
+        pub fn resolve_server_ports(
+            config: &ServerNetworkConfig,
+        ) -> anyhow::Result<ActiveServerPorts> {
+            if config.game_port == config.discovery_port {
+                bail!(
+                    "game and discovery port must differ, both are {}",
+                    config.game_port
+                );
+            }
+
+            let game_port = resolve_port(config.game_port, config.allow_port_fallback)?;
+            if game_port != config.game_port {
+                warn!(
+                    "Game port {} is in use, falling back to {game_port}",
+                    config.game_port
+                );
+            }
+
+            // Clients only probe the configured port, a different one would be as good as none.
+            let discovery_port = validate_port_range(config.discovery_port)?;
+            let discovery_port = if is_server_port_available(discovery_port) {
+                Some(discovery_port)
+            } else if config.allow_port_fallback {
+                warn!("Discovery port {discovery_port} is in use, LAN discovery is off");
+                None
+            } else {
+                bail!("discovery port {discovery_port} is already in use");
+            };
+
+            Ok(ActiveServerPorts {
+                game_port,
+                discovery_port,
+            })
         }
     }
 
-    pub const DISCOVERY_PORT: u16 = 30000;
-    pub const GAME_PORT: u16 = 25571;
-    pub const MAGIC: &[u8] = b"FORGE_DISCOVER_V1";
+    pub mod identity {
+        use {
+            aeronet_webtransport::wtransport::{
+                tls::{Certificate, CertificateChain, PrivateKey},
+                Identity,
+            },
+            bevy::prelude::*,
+            rcgen::{
+                CertificateParams, DistinguishedName, DnType, KeyPair, PKCS_ECDSA_P256_SHA256,
+            },
+            serde::{Deserialize, Serialize},
+            std::{
+                fs, io,
+                path::{Path, PathBuf},
+                time::{SystemTime, UNIX_EPOCH},
+            },
+        };
+
+        pub const IDENTITY_FILE: &str = "server_identity.bin";
+
+        /// WebTransport only accepts self-signed certificates valid for at most 14 days.
+        const VALIDITY_DAYS: u32 = 14;
+        /// Re-issue a bit early so a certificate never expires in the middle of a session.
+        const RENEWAL_MARGIN_SECS: u64 = 24 * 60 * 60;
+        const SUBJECT_ALT_NAMES: [&str; 3] = ["localsingleplayer", "127.0.0.1", "::1"];
 
+        #[derive(Serialize, Deserialize)]
+        struct StoredIdentity {
+            certificate_der: Vec<u8>,
+            private_key_der: Vec<u8>,
+            expires_at: u64,
+        }
+
+        pub fn default_path() -> PathBuf {
+            crate::storage::data_dir().join(IDENTITY_FILE)
+        }
+
+        /// Loads the identity stored at `path`, generating and storing a new one if
+        /// there is none yet.
+        ///
+        /// A certificate that is about to expire is re-issued for the stored key, so the
+        /// SPKI fingerprint clients pinned keeps working.
+        pub fn load_or_generate(path: &Path) -> anyhow::Result<Identity> {
+            match load(path) {
+                Ok(Some(stored)) if stored.expires_at > unix_now() + RENEWAL_MARGIN_SECS => {
+                    return stored.into_identity();
+                }
+                Ok(Some(stored)) => {
+                    info!("Re-issuing the server certificate at {}", path.display());
+                    let key_pair = KeyPair::try_from(stored.private_key_der.as_slice())?;
+                    return issue(path, &key_pair);
+                }
+                Ok(None) => info!("Generating new server identity at {}", path.display()),
+                Err(err) => warn!(
+                    "Discarding unreadable server identity at {}: {err:#}",
+                    path.display()
+                ),
+            }
+            generate(path)
+        }
+
+        /// Unconditionally replaces the key and certificate stored at `path`.
+        pub fn rotate(path: &Path) -> anyhow::Result<Identity> {
+            generate(path)
+        }
+
+        impl StoredIdentity {
+            fn into_identity(self) -> anyhow::Result<Identity> {
+                let certificate = Certificate::from_der(self.certificate_der)?;
+                Ok(Identity::new(
+                    CertificateChain::single(certificate),
+                    PrivateKey::from_der_pkcs8(self.private_key_der),
+                ))
+            }
+        }
+
+        fn load(path: &Path) -> anyhow::Result<Option<StoredIdentity>> {
+            let bytes = match fs::read(path) {
+                Ok(bytes) => bytes,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+                Err(err) => return Err(err.into()),
+            };
+            Ok(Some(postcard::from_bytes(&bytes)?))
+        }
+
+        fn generate(path: &Path) -> anyhow::Result<Identity> {
+            issue(path, &KeyPair::generate_for(&PKCS_ECDSA_P256_SHA256)?)
+        }
+
+        /// Signs a new certificate for `key_pair` and stores both at `path`.
+        fn issue(path: &Path, key_pair: &KeyPair) -> anyhow::Result<Identity> {
+            issue_until(
+                path,
+                key_pair,
+                unix_now() + u64::from(VALIDITY_DAYS) * 24 * 60 * 60,
+            )
+        }
+
+        pub(crate) fn issue_until(
+            path: &Path,
+            key_pair: &KeyPair,
+            expires_at: u64,
+        ) -> anyhow::Result<Identity> {
+            let mut distinguished_name = DistinguishedName::new();
+            distinguished_name.push(DnType::CommonName, "fos_server");
+
+            let mut params = CertificateParams::new(SUBJECT_ALT_NAMES.map(String::from))?;
+            params.distinguished_name = distinguished_name;
+            params.not_before = time::OffsetDateTime::now_utc();
+            params.not_after = time::OffsetDateTime::from_unix_timestamp(expires_at as i64)?;
+            let certificate = params.self_signed(key_pair)?;
+
+            let stored = StoredIdentity {
+                certificate_der: certificate.der().to_vec(),
+                private_key_der: key_pair.serialize_der(),
+                expires_at,
+            };
+            store(path, &stored)?;
+
+            stored.into_identity()
+        }
+
+        fn store(path: &Path, stored: &StoredIdentity) -> anyhow::Result<()> {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+
+            // Write next to the target and rename, so a crash never leaves a truncated key behind.
+            let temporary_path = path.with_extension("tmp");
+            fs::write(&temporary_path, postcard::to_allocvec(stored)?)?;
+            restrict_permissions(&temporary_path)?;
+            fs::rename(&temporary_path, path)?;
+            Ok(())
+        }
+
+        #[cfg(unix)]
+        fn restrict_permissions(path: &Path) -> io::Result<()> {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+        }
+
+        #[cfg(not(unix))]
+        fn restrict_permissions(_path: &Path) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn unix_now() -> u64 {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or_default()
+        }
+    }
+
+    pub use crate::protocol::{discovery::DISCOVERY_PORT, GAME_PORT};
+
+    /// The IPv4 socket, and the IPv6 one if the host has IPv6.
     #[derive(Resource)]
-    struct DiscoverySocket(UdpSocket);
+    struct DiscoverySocket(Vec<UdpSocket>);
 
     pub struct DiscoveryServerPlugin;
 
@@ -337,39 +926,147 @@ pub mod helpers {
 
             app.add_systems(
                 Update,
-                discovery_server_system.run_if(in_state(ServerVisibility::Public)),
+                discovery_server_system
+                    .run_if(in_state(ServerVisibility::Public))
+                    .run_if(resource_exists::<DiscoverySocket>),
             );
         }
     }
 
-    fn insert_discovery_socket(mut commands: Commands) {
-        commands.insert_resource(setup_discovery_socket());
+    fn insert_discovery_socket(
+        mut commands: Commands,
+        ports: Option<Res<super::ActiveServerPorts>>,
+    ) {
+        let Some(port) = ports.map_or(Some(DISCOVERY_PORT), |ports| ports.discovery_port) else {
+            return;
+        };
+        match setup_discovery_socket(port) {
+            Ok(socket) => commands.insert_resource(socket),
+            Err(err) => commands.trigger(super::ServerVisibilityFailed {
+                reason: describe_startup_error(
+                    &anyhow::Error::new(err).context("could not bind the discovery socket"),
+                ),
+            }),
+        }
     }
 
     fn remove_discovery_socket(mut commands: Commands) {
         commands.remove_resource::<DiscoverySocket>();
     }
 
-    fn setup_discovery_socket() -> DiscoverySocket {
-        let socket =
-            UdpSocket::bind(("0.0.0.0", DISCOVERY_PORT)).expect("failed to bind discovery socket");
-        socket
-            .set_broadcast(true)
-            .expect("failed to enable broadcast");
-        socket
-            .set_nonblocking(true)
-            .expect("failed to set nonblocking");
-        DiscoverySocket(socket)
+    fn setup_discovery_socket(port: u16) -> std::io::Result<DiscoverySocket> {
+        let interfaces = if_addrs::get_if_addrs().unwrap_or_default();
+        let socket = UdpSocket::bind(("0.0.0.0", port))?;
+        socket.set_broadcast(true)?;
+        socket.set_nonblocking(true)?;
+        join_multicast_v4(&socket, &interfaces);
+
+        let mut sockets = vec![socket];
+        // Optional, plenty of networks and containers run without IPv6.
+        match bind_discovery_socket_v6(port, &interfaces) {
+            Ok(socket) => sockets.push(socket),
+            Err(err) => warn!("LAN discovery over IPv6 is unavailable: {err}"),
+        }
+        Ok(DiscoverySocket(sockets))
+    }
+
+    /// Joins on every interface, the group would otherwise only be heard on the default one.
+    fn join_multicast_v4(socket: &UdpSocket, interfaces: &[Interface]) {
+        let mut joined = false;
+        for interface in interfaces
+            .iter()
+            .filter(|interface| !interface.is_loopback())
+        {
+            if let IfAddr::V4(addr) = &interface.addr {
+                joined |= socket.join_multicast_v4(&MULTICAST_V4, &addr.ip).is_ok();
+            }
+        }
+        if !joined {
+            if let Err(err) = socket.join_multicast_v4(&MULTICAST_V4, &Ipv4Addr::UNSPECIFIED) {
+                warn!("Could not join the discovery multicast group: {err}");
+            }
+        }
+    }
+
+    fn bind_discovery_socket_v6(port: u16, interfaces: &[Interface]) -> std::io::Result<UdpSocket> {
+        let socket = Socket::new(Domain::IPV6, Type::DGRAM, Some(Protocol::UDP))?;
+        // A dual-stack socket would collide with the IPv4 one on the same port.
+        socket.set_only_v6(true)?;
+        socket.bind(&SocketAddr::from((Ipv6Addr::UNSPECIFIED, port)).into())?;
+        socket.set_nonblocking(true)?;
+        let socket = UdpSocket::from(socket);
+
+        let mut indices: Vec<u32> = interfaces
+            .iter()
+            .filter(|interface| !interface.is_loopback() && interface.ip().is_ipv6())
+            .filter_map(|interface| interface.index)
+            .collect();
+        indices.sort_unstable();
+        indices.dedup();
+        for index in indices {
+            if let Err(err) = socket.join_multicast_v6(&MULTICAST_V6, index) {
+                debug!("Could not join the discovery multicast group on interface {index}: {err}");
+            }
+        }
+        Ok(socket)
     }
 
-    fn discovery_server_system(socket: Res<DiscoverySocket>) {
-        let mut buf = [0u8; 256];
+    /// Whether a discovery request from `ip` may be answered. The reply is many times the
+    /// size of the request, answering the internet would turn a public host into a UDP
+    /// reflection amplifier for spoofed sources.
+    pub fn is_lan_source(ip: IpAddr) -> bool {
+        match ip.to_canonical() {
+            IpAddr::V4(ip) => ip.is_private() || ip.is_link_local() || ip.is_loopback(),
+            IpAddr::V6(ip) => {
+                ip.is_unique_local() || ip.is_unicast_link_local() || ip.is_loopback()
+            }
+        }
+    }
+
+    fn discovery_server_system(
+        sockets: Res<DiscoverySocket>,
+        server_identity: Option<Res<super::ServerIdentity>>,
+        server_info: Res<super::ServerInfo>,
+        policy: Res<super::AdmissionPolicy>,
+        ports: Option<Res<super::ActiveServerPorts>>,
+        remote_clients: Query<(), (With<WebTransportServerClient>, With<Session>)>,
+        local_clients: Query<(), With<LocalClient>>,
+    ) {
+        let response = || DiscoveryResponse {
+            protocol_version: PROTOCOL_VERSION,
+            game_port: ports.as_ref().map_or(GAME_PORT, |ports| ports.game_port),
+            key_fingerprint: server_identity
+                .as_ref()
+                .map(|identity| identity.spki_fingerprint.clone()),
+            server_name: discovery::truncate_name(&server_info.name),
+            game_version: env!("CARGO_PKG_VERSION").to_string(),
+            current_players: (remote_clients.iter().count() + local_clients.iter().count()) as u32,
+            max_players: policy.max_players,
+            password_required: policy.password_required(),
+            world_name: discovery::truncate_name(&server_info.world_name),
+        };
+
+        let mut buf = [0u8; MAX_PACKET_SIZE];
         // alle eingehenden Pakete abarbeiten
-        while let Ok((len, src)) = socket.0.recv_from(&mut buf) {
-            if &buf[..len] == MAGIC {
-                // minimale Antwort: Magic + Port
-                let resp = format!("FORGE_RESP_V1;{}", GAME_PORT);
-                let _ = socket.0.send_to(resp.as_bytes(), src);
+        for socket in &sockets.0 {
+            while let Ok((len, src)) = socket.recv_from(&mut buf) {
+                if !is_lan_source(src.ip()) {
+                    debug!("Ignoring discovery request from {src}, not on the LAN");
+                    continue;
+                }
+                let request = &buf[..len];
+                let reply = if request == REQUEST_MAGIC_V1 {
+                    Some(discovery::encode_response_v1(&response()).into_bytes())
+                } else if discovery::decode_request(request).is_some() {
+                    discovery::encode_response(&response()).ok()
+                } else {
+                    None
+                };
+
+                // The source keeps its scope, so link-local requests are answered on the right interface.
+                if let Some(reply) = reply {
+                    let _ = socket.send_to(&reply, src);
+                }
             }
         }
     }
@@ -378,7 +1075,7 @@ pub mod helpers {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{local::*, status_management::*, FOSServerPlugin};
+    use crate::{local::*, notifications::*, status_management::*};
     use std::fmt::Debug;
 
     /// Extension trait to make tests cleaner and more readable.
@@ -392,9 +1089,6 @@ mod tests {
         fn start_singleplayer_host_new_game(&mut self);
         fn start_singleplayer_host_saved_game(&mut self);
 
-        /// Triggers the stopping sequence via the Game Menu "Exit" event.
-        fn stop_singleplayer(&mut self);
-
         /// Runs the app for a specified number of frames.
         fn wait_frames(&mut self, frames: usize);
 
@@ -409,14 +1103,7 @@ mod tests {
 
     impl ServerVisibilityTestExt for App {
         fn new_test_app() -> Self {
-            let mut app = App::new();
-            app.add_plugins((
-                MinimalPlugins,
-                bevy::input::InputPlugin,
-                bevy::state::app::StatesPlugin,
-                FOSServerPlugin,
-            ));
-            app
+            crate::test_app()
         }
 
         fn start_singleplayer_new_game(&mut self) {
@@ -507,14 +1194,6 @@ mod tests {
             self.update();
         }
 
-        fn stop_singleplayer(&mut self) {
-            // To exit, we must be in the Game Menu or able to trigger the exit action.
-            // We simulate clicking "Exit" in the pause menu.
-            self.world_mut().trigger(PauseMenuEvent::Exit);
-            // Initial update to process the trigger
-            self.update();
-        }
-
         fn wait_frames(&mut self, frames: usize) {
             for _ in 0..frames {
                 self.update();
@@ -544,7 +1223,7 @@ mod tests {
         fn toggle_game_menu(&mut self) {
             let current_focus = {
                 let current_state = self.world().resource::<State<SessionStatus>>();
-                current_state.get().clone()
+                *current_state.get()
             };
             let mut next = self.world_mut().resource_mut::<NextState<SessionStatus>>();
             match current_focus {
@@ -671,6 +1350,8 @@ mod tests {
 
         app.assert_state(ServerVisibility::Public);
         app.assert_entity_count::<WebTransportServer>(1);
+        // The host plays too.
+        app.assert_entity_count::<player::Player>(1);
     }
 
     #[test]
@@ -691,4 +1372,308 @@ mod tests {
         app.assert_state(ServerVisibility::Public);
         app.assert_entity_count::<WebTransportServer>(1);
     }
+
+    #[test]
+    fn server_identity_is_reused_across_loads() {
+        let path = crate::storage::data_dir().join("identity_reused.bin");
+        let _ = std::fs::remove_file(&path);
+
+        let first = ServerIdentity::new(helpers::identity::load_or_generate(&path).unwrap());
+        let second = ServerIdentity::new(helpers::identity::load_or_generate(&path).unwrap());
+
+        assert_eq!(first.cert_hash, second.cert_hash);
+        assert_eq!(first.spki_fingerprint, second.spki_fingerprint);
+    }
+
+    #[test]
+    fn server_identity_rotation_changes_cert_hash() {
+        let path = crate::storage::data_dir().join("identity_rotated.bin");
+        let _ = std::fs::remove_file(&path);
+
+        let original = ServerIdentity::new(helpers::identity::load_or_generate(&path).unwrap());
+        let rotated = ServerIdentity::new(helpers::identity::rotate(&path).unwrap());
+        let reloaded = ServerIdentity::new(helpers::identity::load_or_generate(&path).unwrap());
+
+        assert_ne!(original.cert_hash, rotated.cert_hash);
+        assert_ne!(original.spki_fingerprint, rotated.spki_fingerprint);
+        assert_eq!(rotated.cert_hash, reloaded.cert_hash);
+    }
+
+    #[test]
+    fn expiring_server_certificate_is_reissued_for_the_same_key() {
+        let path = crate::storage::data_dir().join("identity_renewed.bin");
+        let _ = std::fs::remove_file(&path);
+
+        let key_pair = rcgen::KeyPair::generate_for(&rcgen::PKCS_ECDSA_P256_SHA256).unwrap();
+        let expires_soon = time::OffsetDateTime::now_utc().unix_timestamp() as u64 + 60;
+        let expiring = ServerIdentity::new(
+            helpers::identity::issue_until(&path, &key_pair, expires_soon).unwrap(),
+        );
+        let renewed = ServerIdentity::new(helpers::identity::load_or_generate(&path).unwrap());
+        let reloaded = ServerIdentity::new(helpers::identity::load_or_generate(&path).unwrap());
+
+        assert_ne!(expiring.cert_hash, renewed.cert_hash);
+        assert_eq!(expiring.spki_fingerprint, renewed.spki_fingerprint);
+        assert_eq!(renewed.cert_hash, reloaded.cert_hash);
+    }
+
+    #[test]
+    fn port_resolution_falls_back_when_preferred_port_is_taken() {
+        let occupied = std::net::UdpSocket::bind(("0.0.0.0", 0)).unwrap();
+        let occupied_port = occupied.local_addr().unwrap().port();
+
+        let fallback = helpers::ports::resolve_port(occupied_port, true).unwrap();
+        assert_ne!(fallback, occupied_port);
+        assert!(helpers::ports::resolve_port(occupied_port, false).is_err());
+    }
+
+    #[test]
+    fn taken_discovery_port_turns_discovery_off_instead_of_moving_it() {
+        let occupied = std::net::UdpSocket::bind(("0.0.0.0", 0)).unwrap();
+        let occupied_port = occupied.local_addr().unwrap().port();
+        let game_port = helpers::ports::find_free_port().unwrap();
+
+        let mut config = ServerNetworkConfig {
+            game_port,
+            discovery_port: occupied_port,
+            allow_port_fallback: true,
+        };
+        assert_eq!(
+            helpers::ports::resolve_server_ports(&config).unwrap(),
+            ActiveServerPorts {
+                game_port,
+                discovery_port: None,
+            }
+        );
+
+        config.allow_port_fallback = false;
+        assert!(helpers::ports::resolve_server_ports(&config).is_err());
+    }
+
+    #[test]
+    fn discovery_only_answers_the_lan() {
+        for lan in [
+            "192.168.1.10",
+            "10.0.0.2",
+            "172.16.5.4",
+            "169.254.1.1",
+            "127.0.0.1",
+            "fe80::1",
+            "fd12:3456::1",
+            "::1",
+            "::ffff:192.168.1.10",
+        ] {
+            assert!(helpers::is_lan_source(lan.parse().unwrap()), "{lan}");
+        }
+        for internet in ["8.8.8.8", "100.64.0.1", "2001:db8::1", "::ffff:8.8.8.8"] {
+            assert!(
+                !helpers::is_lan_source(internet.parse().unwrap()),
+                "{internet}"
+            );
+        }
+    }
+
+    #[test]
+    fn port_resolution_rejects_privileged_and_clashing_ports() {
+        assert!(helpers::ports::validate_port_range(0).is_err());
+        assert!(helpers::ports::validate_port_range(80).is_err());
+        assert!(helpers::ports::validate_port_range(helpers::GAME_PORT).is_ok());
+
+        let clashing = ServerNetworkConfig {
+            game_port: helpers::GAME_PORT,
+            discovery_port: helpers::GAME_PORT,
+            allow_port_fallback: true,
+        };
+        assert!(helpers::ports::resolve_server_ports(&clashing).is_err());
+    }
+
+    #[test]
+    fn server_rolls_back_to_private_when_game_port_is_taken() {
+        let occupied = std::net::UdpSocket::bind(("0.0.0.0", 0)).unwrap();
+        let occupied_port = occupied.local_addr().unwrap().port();
+
+        let mut app = App::new_test_app();
+        app.insert_resource(ServerNetworkConfig {
+            game_port: occupied_port,
+            discovery_port: helpers::ports::find_free_port().unwrap(),
+            allow_port_fallback: false,
+        });
+        app.start_singleplayer_host_new_game();
+        app.wait_frames(3);
+
+        app.assert_state(SingleplayerStatus::Running);
+        app.assert_state(ServerVisibility::Private);
+        app.assert_entity_count::<WebTransportServer>(0);
+        let notifications = app.world().resource::<NotificationQueue>();
+        assert!(notifications
+            .messages
+            .iter()
+            .any(|note| note.type_ == NotificationType::Error
+                && note.message.contains(&occupied_port.to_string())));
+    }
+
+    #[test]
+    fn shutdown_waits_for_the_countdown_only_with_remote_clients() {
+        let mut world = World::new();
+        assert!(world.run_system_cached(shutdown_countdown_over).unwrap());
+        world.insert_resource(PendingShutdown {
+            reason: "Server closing".to_string(),
+            timer: Timer::new(Duration::from_secs(5), TimerMode::Once),
+        });
+        assert!(!world.run_system_cached(shutdown_countdown_over).unwrap());
+        world
+            .resource_mut::<PendingShutdown>()
+            .timer
+            .tick(Duration::from_secs(5));
+        assert!(world.run_system_cached(shutdown_countdown_over).unwrap());
+
+        let notice = ServerShutdown {
+            reason: "Server closing".to_string(),
+            countdown: Some(Duration::from_millis(4200)),
+        };
+        assert_eq!(notice.describe(), "Server closing in 5 seconds");
+
+        let mut app = App::new_test_app();
+        app.start_singleplayer_host_new_game();
+        app.world_mut().trigger(NotifyServerShutdown {
+            reason: "Server closing".to_string(),
+        });
+        app.update();
+        assert!(!app.world().contains_resource::<PendingShutdown>());
+    }
+
+    /// Runs both apps until `done` holds for the host, or panics after `timeout`.
+    #[cfg(feature = "client")]
+    fn run_until(
+        host: &mut App,
+        guest: &mut App,
+        timeout: Duration,
+        mut done: impl FnMut(&mut App, &mut App) -> bool,
+    ) {
+        let deadline = std::time::Instant::now() + timeout;
+        while !done(host, guest) {
+            assert!(std::time::Instant::now() < deadline, "timed out");
+            host.update();
+            guest.update();
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "client")]
+    fn remote_players_get_the_whole_countdown_before_the_host_stops() {
+        use crate::client::{ServerShutdownCountdown, SetClientTarget};
+
+        let countdown = Duration::from_secs(2);
+        let mut host = App::new_test_app();
+        host.insert_resource(ShutdownCountdown(countdown))
+            .insert_resource(ServerNetworkConfig {
+                game_port: helpers::ports::find_free_port().unwrap(),
+                discovery_port: helpers::ports::find_free_port().unwrap(),
+                allow_port_fallback: false,
+            });
+        host.start_singleplayer_host_new_game();
+        host.wait_frames(3);
+        host.assert_state(ServerVisibility::Public);
+        let game_port = host.world().resource::<ActiveServerPorts>().game_port;
+
+        let mut guest = crate::test_app();
+        guest.update();
+        // Both apps load the same profile from the test data dir, the host would see its own id.
+        guest.insert_resource(PlayerProfile {
+            name: "Guest".to_string(),
+            ..default()
+        });
+        guest
+            .world_mut()
+            .trigger(MainMenuInteraction::SwitchContext(
+                MainMenuContext::Multiplayer,
+            ));
+        guest.update();
+        guest
+            .world_mut()
+            .trigger(SetMultiplayerMenu::Navigate(MultiplayerSetup::JoinGame));
+        guest.update();
+        guest.world_mut().commands().queue(SetClientTarget {
+            input: format!("127.0.0.1:{game_port}"),
+            key_fingerprint: None,
+        });
+        guest.update();
+        guest.world_mut().trigger(SetJoinGame::Confirm);
+
+        run_until(
+            &mut host,
+            &mut guest,
+            Duration::from_secs(10),
+            |_, guest| {
+                guest
+                    .world()
+                    .get_resource::<State<ClientStatus>>()
+                    .is_some_and(|status| *status.get() == ClientStatus::Running)
+            },
+        );
+
+        host.world_mut().trigger(SetSingleplayerStatus {
+            transition: SingleplayerStatus::Stopping,
+        });
+        let stopped_at = std::time::Instant::now();
+        run_until(&mut host, &mut guest, Duration::from_secs(1), |_, guest| {
+            guest.world().contains_resource::<ServerShutdownCountdown>()
+        });
+
+        // Halfway through, the guest is still there and watches the countdown go down.
+        while stopped_at.elapsed() < countdown / 2 {
+            host.update();
+            guest.update();
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        host.assert_state(SingleplayerShutdownStep::DisconnectRemoteClients);
+        assert_eq!(
+            host.world_mut()
+                .query_filtered::<(), (With<WebTransportServerClient>, With<aeronet::io::Session>)>(
+                )
+                .iter(host.world())
+                .count(),
+            1
+        );
+        let remaining = guest
+            .world()
+            .resource::<ServerShutdownCountdown>()
+            .timer
+            .remaining();
+        assert!(!remaining.is_zero() && remaining < countdown - countdown / 4);
+
+        run_until(&mut host, &mut guest, Duration::from_secs(10), |host, _| {
+            *host.world().resource::<State<SessionType>>().get() == SessionType::None
+        });
+        assert!(stopped_at.elapsed() >= countdown);
+        run_until(&mut host, &mut guest, Duration::from_secs(5), |_, guest| {
+            *guest.world().resource::<State<SessionType>>().get() == SessionType::None
+        });
+        assert!(!guest.world().contains_resource::<ServerShutdownCountdown>());
+    }
+
+    #[test]
+    fn authorized_clients_are_told_when_the_world_is_synced() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_message::<ToClients<SyncComplete>>()
+            .add_observer(on_server_client_authorized);
+        app.world_mut().spawn(Replicated);
+        app.world_mut().spawn(Replicated);
+
+        let client = app.world_mut().spawn(AuthorizedClient).id();
+        app.update();
+
+        let sent: Vec<_> = app
+            .world_mut()
+            .resource_mut::<Messages<ToClients<SyncComplete>>>()
+            .drain()
+            .collect();
+        assert_eq!(sent.len(), 1);
+        assert!(
+            matches!(sent[0].mode, SendMode::Direct(ClientId::Client(entity)) if entity == client)
+        );
+        assert_eq!(sent[0].message, SyncComplete { entities: 2 });
+    }
 }
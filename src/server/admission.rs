@@ -0,0 +1,502 @@
+use {
+    crate::{
+        local::LocalClient,
+        player::PlayerIdentity,
+        protocol::handshake::{self, GAME_VERSION_HEADER, PASSWORD_HEADER, PLAYER_ID_HEADER},
+        status_management::ServerVisibility,
+        storage,
+    },
+    aeronet::io::{connection::Disconnect, Session},
+    aeronet_webtransport::server::{SessionRequest, WebTransportServerClient},
+    anyhow::Context,
+    bevy::prelude::*,
+    bevy_replicon::prelude::AuthorizedClient,
+    sha2::{Digest, Sha256},
+    std::{
+        collections::{BTreeSet, HashMap},
+        fmt,
+        path::{Path, PathBuf},
+    },
+};
+
+const BANNED_PLAYERS_FILE: &str = "banned_players.txt";
+const ALLOWED_PLAYERS_FILE: &str = "allowed_players.txt";
+
+pub struct AdmissionPlugin;
+
+impl Plugin for AdmissionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AdmissionPolicy>()
+            .add_systems(OnEnter(ServerVisibility::GoingPublic), reload_player_lists)
+            .add_observer(on_rejected_session_established)
+            .add_observer(keep_rejected_session_unauthorized)
+            .add_observer(on_ban_player)
+            .add_observer(on_unban_player)
+            .add_observer(on_allow_player)
+            .add_observer(on_disallow_player);
+    }
+}
+
+/// Decides which clients may join the public server.
+///
+/// Password and player cap are per-session settings, the ban and allow lists are
+/// persisted next to the server identity and re-read whenever the server goes public.
+///
+/// Both lists hold player ids, which clients generate themselves and nothing vouches for.
+/// They keep out a player who is unwilling to reset their profile, but a ban is advisory:
+/// a fresh profile gets past it, and anyone who learns an allowed id gets in.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct AdmissionPolicy {
+    pub password: Option<String>,
+    /// Includes the host, 0 means unlimited.
+    pub max_players: u32,
+    /// Only players on [`Self::allowed`] may join.
+    pub allow_list_enabled: bool,
+    pub banned: BTreeSet<String>,
+    pub allowed: BTreeSet<String>,
+}
+
+impl AdmissionPolicy {
+    pub fn password_required(&self) -> bool {
+        self.password
+            .as_deref()
+            .is_some_and(|password| !password.is_empty())
+    }
+
+    /// Checks the headers of a session request, `current_players` must not include the requester.
+    pub fn check(
+        &self,
+        headers: &HashMap<String, String>,
+        current_players: u32,
+    ) -> Result<(), AdmissionRejection> {
+        let player_id = headers.get(PLAYER_ID_HEADER).map(|id| id.trim());
+
+        if player_id.is_some_and(|id| self.banned.contains(id)) {
+            return Err(AdmissionRejection::Banned);
+        }
+        if self.allow_list_enabled && !player_id.is_some_and(|id| self.allowed.contains(id)) {
+            return Err(AdmissionRejection::NotOnAllowList);
+        }
+        if self.password_required() {
+            let attempt = headers.get(PASSWORD_HEADER).map_or("", String::as_str);
+            if !passwords_match(attempt, self.password.as_deref().unwrap_or_default()) {
+                return Err(AdmissionRejection::WrongPassword);
+            }
+        }
+        if self.max_players > 0 && current_players >= self.max_players {
+            return Err(AdmissionRejection::ServerFull);
+        }
+        Ok(())
+    }
+}
+
+/// Compares digests in constant time, so neither the response time nor the length of the
+/// attempt tells how much of the password was right.
+pub(super) fn passwords_match(attempt: &str, password: &str) -> bool {
+    let attempt = Sha256::digest(attempt.as_bytes());
+    let password = Sha256::digest(password.as_bytes());
+    subtle::ConstantTimeEq::ct_eq(attempt.as_slice(), password.as_slice()).into()
+}
+
+/// Why a client was turned away, the [`fmt::Display`] text is what the client gets to see.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdmissionRejection {
+    VersionMismatch,
+    InvalidPlayerIdentity,
+    AlreadyConnected,
+    Banned,
+    NotOnAllowList,
+    WrongPassword,
+    ServerFull,
+}
+
+impl fmt::Display for AdmissionRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::VersionMismatch => {
+                return write!(
+                    f,
+                    "Server is running version {} (protocol {})",
+                    env!("CARGO_PKG_VERSION"),
+                    handshake::PROTOCOL_VERSION
+                );
+            }
+            Self::InvalidPlayerIdentity => "Invalid player id or name",
+            Self::AlreadyConnected => "A player with your id is already connected",
+            Self::Banned => "You are banned from this server",
+            Self::NotOnAllowList => "You are not on this server's allow list",
+            Self::WrongPassword => "Wrong server password",
+            Self::ServerFull => "Server is full",
+        })
+    }
+}
+
+/// Marks a client that gets disconnected with the reason as soon as its session is up.
+///
+/// A WebTransport `403` carries no body, so rejecting the session request outright
+/// would leave the player guessing why. The session only exists to deliver the reason:
+/// it is disconnected in the frame it comes up and never becomes an [`AuthorizedClient`],
+/// so it gets no replication and no messages, and without a [`PlayerIdentity`] no player.
+#[derive(Component, Debug)]
+pub struct RejectedSession(pub AdmissionRejection);
+
+/// Adds a player id to [`AdmissionPolicy::banned`], see there for what that does and doesn't stop.
+#[derive(Event, Debug, Clone)]
+pub struct BanPlayer {
+    pub player_id: String,
+}
+
+#[derive(Event, Debug, Clone)]
+pub struct UnbanPlayer {
+    pub player_id: String,
+}
+
+#[derive(Event, Debug, Clone)]
+pub struct AllowPlayer {
+    pub player_id: String,
+}
+
+#[derive(Event, Debug, Clone)]
+pub struct DisallowPlayer {
+    pub player_id: String,
+}
+
+/// Attaches the [`PlayerIdentity`] to the client behind `trigger`, or marks it with
+/// [`RejectedSession`] if it is turned away.
+///
+/// A player id is only admitted once. A client whose connection broke gets its session
+/// dropped after [`crate::network_stats::MAX_IDLE_TIMEOUT`], which its reconnect attempts
+/// outlast, and then takes its parked player back.
+pub(super) fn admit_session(
+    trigger: &On<SessionRequest>,
+    commands: &mut Commands,
+    protocol_hash: &str,
+    policy: &AdmissionPolicy,
+    remote_clients: &Query<(), With<WebTransportServerClient>>,
+    local_clients: &Query<(), With<LocalClient>>,
+    identities: &Query<&PlayerIdentity>,
+) {
+    let client = trigger.event_target();
+    // Clients still connecting count too, otherwise simultaneous joins overshoot the cap.
+    let current_players = remote_clients
+        .iter()
+        .count()
+        .saturating_sub(1)
+        .saturating_add(local_clients.iter().count()) as u32;
+
+    // Checked first, an outdated client might not even send its identity the same way.
+    if !handshake::is_same_protocol(&trigger.headers, protocol_hash) {
+        let client_version = trigger.headers.get(GAME_VERSION_HEADER);
+        info!("Rejecting {client}: protocol mismatch, client runs version {client_version:?}");
+        commands
+            .entity(client)
+            .insert(RejectedSession(AdmissionRejection::VersionMismatch));
+        return;
+    }
+
+    let admission = admit_identity(&trigger.headers, policy, current_players, identities);
+    match admission {
+        Ok(identity) => {
+            info!("{} ({}) joins as {client}", identity.name, identity.id);
+            commands.entity(client).insert(identity);
+        }
+        Err(rejection) => {
+            info!("Rejecting {client}: {rejection}");
+            commands.entity(client).insert(RejectedSession(rejection));
+        }
+    }
+}
+
+/// The identity a session request may join with, unless its id is already on the server.
+fn admit_identity<'a>(
+    headers: &HashMap<String, String>,
+    policy: &AdmissionPolicy,
+    current_players: u32,
+    connected: impl IntoIterator<Item = &'a PlayerIdentity>,
+) -> Result<PlayerIdentity, AdmissionRejection> {
+    let identity =
+        PlayerIdentity::from_headers(headers).ok_or(AdmissionRejection::InvalidPlayerIdentity)?;
+    if connected
+        .into_iter()
+        .any(|connected| connected.id == identity.id)
+    {
+        return Err(AdmissionRejection::AlreadyConnected);
+    }
+    policy.check(headers, current_players)?;
+    Ok(identity)
+}
+
+fn on_rejected_session_established(
+    trigger: On<Add, Session>,
+    rejected: Query<&RejectedSession>,
+    mut commands: Commands,
+) {
+    let client = trigger.event_target();
+    if let Ok(RejectedSession(rejection)) = rejected.get(client) {
+        commands.trigger(Disconnect::new(client, rejection.to_string()));
+    }
+}
+
+/// Replicon authorizes clients whose protocol hash matches, which a rejected client could
+/// still send before its disconnect is processed.
+fn keep_rejected_session_unauthorized(
+    trigger: On<Add, AuthorizedClient>,
+    rejected: Query<(), With<RejectedSession>>,
+    mut commands: Commands,
+) {
+    let client = trigger.event_target();
+    if rejected.contains(client) {
+        commands.entity(client).remove::<AuthorizedClient>();
+    }
+}
+
+fn reload_player_lists(mut policy: ResMut<AdmissionPolicy>) {
+    let dir = storage::data_dir();
+    match load_player_list(&dir.join(BANNED_PLAYERS_FILE)) {
+        Ok(banned) => policy.banned = banned,
+        Err(err) => warn!("Keeping the current ban list: {err:#}"),
+    }
+    match load_player_list(&dir.join(ALLOWED_PLAYERS_FILE)) {
+        Ok(allowed) => policy.allowed = allowed,
+        Err(err) => warn!("Keeping the current allow list: {err:#}"),
+    }
+}
+
+fn on_ban_player(trigger: On<BanPlayer>, mut policy: ResMut<AdmissionPolicy>) {
+    if policy.banned.insert(trigger.player_id.clone()) {
+        info!("Banned player {}", trigger.player_id);
+        persist(BANNED_PLAYERS_FILE, &policy.banned);
+    }
+}
+
+fn on_unban_player(trigger: On<UnbanPlayer>, mut policy: ResMut<AdmissionPolicy>) {
+    if policy.banned.remove(&trigger.player_id) {
+        info!("Unbanned player {}", trigger.player_id);
+        persist(BANNED_PLAYERS_FILE, &policy.banned);
+    }
+}
+
+fn on_allow_player(trigger: On<AllowPlayer>, mut policy: ResMut<AdmissionPolicy>) {
+    if policy.allowed.insert(trigger.player_id.clone()) {
+        info!("Added player {} to the allow list", trigger.player_id);
+        persist(ALLOWED_PLAYERS_FILE, &policy.allowed);
+    }
+}
+
+fn on_disallow_player(trigger: On<DisallowPlayer>, mut policy: ResMut<AdmissionPolicy>) {
+    if policy.allowed.remove(&trigger.player_id) {
+        info!("Removed player {} from the allow list", trigger.player_id);
+        persist(ALLOWED_PLAYERS_FILE, &policy.allowed);
+    }
+}
+
+fn persist(file_name: &str, players: &BTreeSet<String>) {
+    if let Err(err) = store_player_list(&storage::data_dir().join(file_name), players) {
+        error!("Failed to persist {file_name}: {err:#}");
+    }
+}
+
+/// One player id per line, so hosts can edit the lists by hand. `#` starts a comment.
+pub fn load_player_list(path: &Path) -> anyhow::Result<BTreeSet<String>> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(BTreeSet::new()),
+        Err(err) => {
+            return Err(err).with_context(|| format!("reading {}", path.display()));
+        }
+    };
+
+    Ok(content
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|id| !id.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+pub fn store_player_list(path: &Path, players: &BTreeSet<String>) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    }
+
+    let mut content = String::new();
+    for player in players {
+        content.push_str(player);
+        content.push('\n');
+    }
+
+    let tmp_path = PathBuf::from(format!("{}.tmp", path.display()));
+    std::fs::write(&tmp_path, content)
+        .with_context(|| format!("writing {}", tmp_path.display()))?;
+    std::fs::rename(&tmp_path, path).with_context(|| format!("replacing {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, uuid::Uuid};
+
+    fn headers(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn protocol_mismatch_is_detected_and_names_the_server_version() {
+        let hash = "00ff";
+        let version = handshake::PROTOCOL_VERSION.to_string();
+        let matching = headers(&[
+            (handshake::PROTOCOL_VERSION_HEADER, &version),
+            (handshake::PROTOCOL_HASH_HEADER, hash),
+        ]);
+        assert!(handshake::is_same_protocol(&matching, hash));
+        assert!(!handshake::is_same_protocol(&matching, "0100"));
+        assert!(!handshake::is_same_protocol(&HashMap::new(), hash));
+
+        let reason = AdmissionRejection::VersionMismatch.to_string();
+        assert!(reason.starts_with(&format!(
+            "Server is running version {}",
+            env!("CARGO_PKG_VERSION")
+        )));
+    }
+
+    #[test]
+    fn open_policy_admits_everyone() {
+        let policy = AdmissionPolicy::default();
+        assert_eq!(policy.check(&HashMap::new(), 100), Ok(()));
+    }
+
+    #[test]
+    fn policy_rejects_wrong_password_full_server_and_banned_players() {
+        let policy = AdmissionPolicy {
+            password: Some("secret".to_string()),
+            max_players: 2,
+            banned: BTreeSet::from(["griefer".to_string()]),
+            ..default()
+        };
+
+        let welcome = headers(&[(PASSWORD_HEADER, "secret"), (PLAYER_ID_HEADER, "friend")]);
+        assert_eq!(policy.check(&welcome, 1), Ok(()));
+        assert_eq!(
+            policy.check(&headers(&[(PASSWORD_HEADER, "guess")]), 1),
+            Err(AdmissionRejection::WrongPassword)
+        );
+        assert_eq!(
+            policy.check(&HashMap::new(), 1),
+            Err(AdmissionRejection::WrongPassword)
+        );
+        assert_eq!(
+            policy.check(&welcome, 2),
+            Err(AdmissionRejection::ServerFull)
+        );
+
+        let banned = headers(&[(PASSWORD_HEADER, "secret"), (PLAYER_ID_HEADER, "griefer")]);
+        assert_eq!(policy.check(&banned, 0), Err(AdmissionRejection::Banned));
+    }
+
+    #[test]
+    fn passwords_only_match_exactly() {
+        assert!(passwords_match("secret", "secret"));
+        assert!(!passwords_match("secre", "secret"));
+        assert!(!passwords_match("secret ", "secret"));
+        assert!(!passwords_match("", "secret"));
+    }
+
+    #[test]
+    fn rejected_sessions_are_never_authorized() {
+        let mut app = crate::test_app();
+        let rejected = app
+            .world_mut()
+            .spawn(RejectedSession(AdmissionRejection::Banned))
+            .id();
+        let admitted = app.world_mut().spawn_empty().id();
+
+        app.world_mut()
+            .entity_mut(rejected)
+            .insert(AuthorizedClient);
+        app.world_mut()
+            .entity_mut(admitted)
+            .insert(AuthorizedClient);
+        app.world_mut().flush();
+
+        assert!(app.world().get::<AuthorizedClient>(rejected).is_none());
+        assert!(app.world().get::<AuthorizedClient>(admitted).is_some());
+    }
+
+    #[test]
+    fn connected_player_ids_are_not_admitted_again() {
+        let ada = PlayerIdentity {
+            id: Uuid::new_v4(),
+            name: "Ada".to_string(),
+        };
+        let policy = AdmissionPolicy {
+            password: Some("secret".to_string()),
+            ..default()
+        };
+        let request = |identity: &PlayerIdentity| {
+            let id = identity.id.to_string();
+            headers(&[
+                (PLAYER_ID_HEADER, &id),
+                (handshake::PLAYER_NAME_HEADER, &identity.name),
+                (PASSWORD_HEADER, "secret"),
+            ])
+        };
+
+        // Knowing the id and the password is not enough to take over a live session.
+        assert_eq!(
+            admit_identity(&request(&ada), &policy, 1, [&ada]),
+            Err(AdmissionRejection::AlreadyConnected)
+        );
+        let grace = PlayerIdentity {
+            id: Uuid::new_v4(),
+            name: "Grace".to_string(),
+        };
+        assert_eq!(
+            admit_identity(&request(&grace), &policy, 1, [&ada]),
+            Ok(grace.clone())
+        );
+        // Once the old session is gone the player is welcome back.
+        assert_eq!(
+            admit_identity(&request(&ada), &policy, 1, [&grace]),
+            Ok(ada)
+        );
+    }
+
+    #[test]
+    fn allow_list_requires_a_known_player_id() {
+        let policy = AdmissionPolicy {
+            allow_list_enabled: true,
+            allowed: BTreeSet::from(["friend".to_string()]),
+            ..default()
+        };
+
+        assert_eq!(
+            policy.check(&headers(&[(PLAYER_ID_HEADER, "friend")]), 0),
+            Ok(())
+        );
+        assert_eq!(
+            policy.check(&headers(&[(PLAYER_ID_HEADER, "stranger")]), 0),
+            Err(AdmissionRejection::NotOnAllowList)
+        );
+        assert_eq!(
+            policy.check(&HashMap::new(), 0),
+            Err(AdmissionRejection::NotOnAllowList)
+        );
+    }
+
+    #[test]
+    fn player_lists_round_trip_through_disk() {
+        let path = storage::data_dir().join("admission_round_trip.txt");
+        let players = BTreeSet::from(["alice".to_string(), "bob".to_string()]);
+
+        store_player_list(&path, &players).unwrap();
+        assert_eq!(load_player_list(&path).unwrap(), players);
+
+        std::fs::write(&path, "# banned for griefing\nalice # again\n\n  bob  \n").unwrap();
+        assert_eq!(load_player_list(&path).unwrap(), players);
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(load_player_list(&path).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,469 @@
+use {
+    super::admission::{BanPlayer, UnbanPlayer},
+    crate::{
+        local::LocalClient,
+        player::{PlayerIdentity, PlayerProfile},
+        protocol::ServerChat,
+        status_management::{
+            ServerVisibility, SessionType, SetServerVisibility, SetSingleplayerStatus,
+            SingleplayerStatus,
+        },
+    },
+    aeronet::io::connection::Disconnect,
+    aeronet_webtransport::server::WebTransportServerClient,
+    bevy::prelude::*,
+    bevy_replicon::prelude::*,
+    std::{
+        collections::VecDeque,
+        io::BufRead,
+        sync::{
+            mpsc::{self, Receiver, TryRecvError},
+            Mutex,
+        },
+    },
+    uuid::Uuid,
+};
+
+/// Lines kept for the egui panel, older ones are dropped.
+const CONSOLE_HISTORY: usize = 200;
+
+const HELP: &str =
+    "Commands: list, kick <player>, ban <player or id>, unban <player id>, say <message>, \
+visibility private|public, save, stop, help";
+
+pub struct AdminConsolePlugin;
+
+impl Plugin for AdminConsolePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ConsoleLog>()
+            .add_observer(on_run_admin_command);
+    }
+}
+
+/// Feeds lines typed on stdin into [`RunAdminCommand`], for servers without a UI.
+pub struct StdinConsolePlugin;
+
+impl Plugin for StdinConsolePlugin {
+    fn build(&self, app: &mut App) {
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in std::io::stdin().lock().lines() {
+                let Ok(line) = line else { break };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        app.insert_resource(StdinConsole(Mutex::new(receiver)))
+            .add_systems(Update, read_stdin_console);
+    }
+}
+
+/// One line of admin input, e.g. `kick Alice`.
+#[derive(Event, Debug, Clone)]
+pub struct RunAdminCommand {
+    pub line: String,
+    pub source: CommandSource,
+}
+
+/// Where an admin command came from, so the answer can find its way back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandSource {
+    /// Stdin or the host's egui panel, answered through the [`ConsoleLog`].
+    Console,
+    /// A remote admin connection, see [`super::rcon`].
+    Rcon(u64),
+}
+
+/// Output of a [`RunAdminCommand`], or why it failed.
+#[derive(Event, Debug, Clone)]
+pub struct AdminCommandCompleted {
+    pub source: CommandSource,
+    pub outcome: Result<Vec<String>, String>,
+}
+
+/// Input echo and command output, newest last.
+#[derive(Resource, Debug, Default)]
+pub struct ConsoleLog {
+    pub lines: VecDeque<String>,
+}
+
+impl ConsoleLog {
+    pub fn push(&mut self, line: impl Into<String>) {
+        let line = line.into();
+        info!("Console: {line}");
+        self.lines.push_back(line);
+        while self.lines.len() > CONSOLE_HISTORY {
+            self.lines.pop_front();
+        }
+    }
+}
+
+#[derive(Resource)]
+struct StdinConsole(Mutex<Receiver<String>>);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AdminCommand {
+    List,
+    Kick(String),
+    Ban(String),
+    Unban(String),
+    Say(String),
+    Visibility(ServerVisibility),
+    Save,
+    Stop,
+    Help,
+}
+
+impl AdminCommand {
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let line = line.trim();
+        let (name, argument) = line
+            .split_once(char::is_whitespace)
+            .map_or((line, ""), |(name, argument)| (name, argument.trim()));
+        let required = |usage: &str| {
+            if argument.is_empty() {
+                Err(format!("Usage: {usage}"))
+            } else {
+                Ok(argument.to_string())
+            }
+        };
+
+        match name.to_lowercase().as_str() {
+            "list" => Ok(Self::List),
+            "kick" => required("kick <player>").map(Self::Kick),
+            "ban" => required("ban <player or id>").map(Self::Ban),
+            "unban" => required("unban <player id>").map(Self::Unban),
+            "say" => required("say <message>").map(Self::Say),
+            "visibility" => match argument {
+                "private" => Ok(Self::Visibility(ServerVisibility::GoingPrivate)),
+                "public" => Ok(Self::Visibility(ServerVisibility::GoingPublic)),
+                _ => Err("Usage: visibility private|public".to_string()),
+            },
+            "save" => Ok(Self::Save),
+            "stop" => Ok(Self::Stop),
+            "help" | "?" => Ok(Self::Help),
+            _ => Err(format!("Unknown command {name:?}, try help")),
+        }
+    }
+}
+
+/// A connected player, matched by name (case-insensitive) or id.
+struct ConnectedPlayer {
+    client: Entity,
+    identity: PlayerIdentity,
+    is_host: bool,
+}
+
+/// The one player `target` names, names aren't unique so several matches are an error.
+fn find_player(
+    target: &str,
+    clients: &Query<(Entity, &PlayerIdentity, Has<LocalClient>)>,
+) -> Result<Option<ConnectedPlayer>, String> {
+    let mut matches: Vec<_> = clients
+        .iter()
+        .filter(|(_, identity, _)| {
+            identity.name.eq_ignore_ascii_case(target) || identity.id.to_string() == target
+        })
+        .map(|(client, identity, is_host)| ConnectedPlayer {
+            client,
+            identity: identity.clone(),
+            is_host,
+        })
+        .collect();
+    if matches.len() > 1 {
+        let ids: Vec<_> = matches
+            .iter()
+            .map(|player| player.identity.id.to_string())
+            .collect();
+        return Err(format!(
+            "{} players are named {target:?}, use one of their ids: {}",
+            matches.len(),
+            ids.join(", ")
+        ));
+    }
+    Ok(matches.pop())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn on_run_admin_command(
+    trigger: On<RunAdminCommand>,
+    mut commands: Commands,
+    mut log: ResMut<ConsoleLog>,
+    session_type: Res<State<SessionType>>,
+    visibility: Option<Res<State<ServerVisibility>>>,
+    clients: Query<(Entity, &PlayerIdentity, Has<LocalClient>)>,
+    remote_clients: Query<(), With<WebTransportServerClient>>,
+    host_profile: Res<PlayerProfile>,
+    mut server_chat: MessageWriter<ToClients<ServerChat>>,
+) {
+    let line = trigger.line.trim();
+    if line.is_empty() {
+        return;
+    }
+    log.push(format!("> {line}"));
+
+    let outcome = AdminCommand::parse(line).and_then(|command| {
+        if *session_type.get() != SessionType::Singleplayer && command != AdminCommand::Help {
+            return Err("No game is being hosted".to_string());
+        }
+
+        match command {
+            AdminCommand::List => {
+                let mut output = vec![format!("{} players online", clients.iter().count())];
+                for (client, identity, is_host) in &clients {
+                    let role = if is_host { " (host)" } else { "" };
+                    output.push(format!(
+                        "  {}{role} - {} - {client}",
+                        identity.name, identity.id
+                    ));
+                }
+                Ok(output)
+            }
+            AdminCommand::Kick(target) => match find_player(&target, &clients)? {
+                Some(player) if player.is_host || !remote_clients.contains(player.client) => {
+                    Err("The host can't be kicked, use stop instead".to_string())
+                }
+                Some(player) => {
+                    commands.trigger(Disconnect::new(player.client, "Kicked by the host"));
+                    Ok(vec![format!("Kicked {}", player.identity.name)])
+                }
+                None => Err(format!("No player named {target:?} is online")),
+            },
+            AdminCommand::Ban(target) => match find_player(&target, &clients)? {
+                Some(player) if player.is_host => Err("The host can't be banned".to_string()),
+                Some(player) => {
+                    commands.trigger(BanPlayer {
+                        player_id: player.identity.id.to_string(),
+                    });
+                    if remote_clients.contains(player.client) {
+                        commands.trigger(Disconnect::new(
+                            player.client,
+                            "You are banned from this server",
+                        ));
+                    }
+                    Ok(vec![format!(
+                        "Banned {} ({})",
+                        player.identity.name, player.identity.id
+                    )])
+                }
+                // Offline players can only be banned by id, names aren't remembered.
+                None => match Uuid::parse_str(&target) {
+                    Ok(id) if !id.is_nil() => {
+                        // Clients send the hyphenated lowercase form, store the same.
+                        commands.trigger(BanPlayer {
+                            player_id: id.to_string(),
+                        });
+                        Ok(vec![format!("Banned player id {id}")])
+                    }
+                    _ => Err(format!(
+                        "No player named {target:?} is online; ban by id"
+                    )),
+                },
+            },
+            AdminCommand::Unban(player_id) => {
+                commands.trigger(UnbanPlayer {
+                    player_id: player_id.clone(),
+                });
+                Ok(vec![format!("Unbanned player id {player_id}")])
+            }
+            AdminCommand::Say(text) => {
+                server_chat.write(ToClients {
+                    mode: SendMode::Broadcast,
+                    message: ServerChat {
+                        sender: format!("[Host] {}", host_profile.name),
+                        text: text.clone(),
+                    },
+                });
+                Ok(vec![format!("[Host] {text}")])
+            }
+            AdminCommand::Visibility(transition) => {
+                let current = visibility.map(|state| *state.get());
+                let allowed = match transition {
+                    ServerVisibility::GoingPublic => current == Some(ServerVisibility::Private),
+                    _ => current == Some(ServerVisibility::Public),
+                };
+                if !allowed {
+                    return Err(format!(
+                        "Server is {current:?}, can't switch to {transition:?}"
+                    ));
+                }
+                commands.trigger(SetServerVisibility { transition });
+                Ok(vec![format!("Server visibility: {transition:?}")])
+            }
+            AdminCommand::Save => Ok(vec![
+                "Nothing to save, worlds are not persisted yet. Ban and allow lists are saved as they change".to_string(),
+            ]),
+            AdminCommand::Stop => {
+                // Entering `Stopping` starts the shutdown steps, remote players get the countdown.
+                commands.trigger(SetSingleplayerStatus {
+                    transition: SingleplayerStatus::Stopping,
+                });
+                Ok(vec!["Stopping the server".to_string()])
+            }
+            AdminCommand::Help => Ok(vec![HELP.to_string()]),
+        }
+    });
+
+    match &outcome {
+        Ok(output) => output.iter().for_each(|line| log.push(line.clone())),
+        Err(err) => log.push(err.clone()),
+    }
+    commands.trigger(AdminCommandCompleted {
+        source: trigger.source,
+        outcome,
+    });
+}
+
+fn read_stdin_console(mut commands: Commands, console: Res<StdinConsole>) {
+    let Ok(receiver) = console.0.lock() else {
+        return;
+    };
+    loop {
+        match receiver.try_recv() {
+            Ok(line) => commands.trigger(RunAdminCommand {
+                line,
+                source: CommandSource::Console,
+            }),
+            Err(TryRecvError::Empty) => break,
+            // Stdin is closed, e.g. when running as a service, nothing more will arrive.
+            Err(TryRecvError::Disconnected) => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::server::AdmissionPolicy};
+
+    #[test]
+    fn parses_commands_and_rejects_missing_arguments() {
+        assert_eq!(AdminCommand::parse(" LIST "), Ok(AdminCommand::List));
+        assert_eq!(
+            AdminCommand::parse("say hello  everyone"),
+            Ok(AdminCommand::Say("hello  everyone".to_string()))
+        );
+        assert_eq!(
+            AdminCommand::parse("visibility public"),
+            Ok(AdminCommand::Visibility(ServerVisibility::GoingPublic))
+        );
+        assert!(AdminCommand::parse("kick").is_err());
+        assert!(AdminCommand::parse("visibility hidden").is_err());
+        assert!(AdminCommand::parse("teleport Alice").is_err());
+    }
+
+    fn hosting_app() -> App {
+        let mut app = crate::test_app();
+        app.update();
+
+        app.world_mut()
+            .resource_mut::<NextState<SessionType>>()
+            .set(SessionType::Singleplayer);
+        for _ in 0..3 {
+            app.update();
+        }
+        assert_eq!(
+            *app.world().resource::<State<SingleplayerStatus>>().get(),
+            SingleplayerStatus::Running
+        );
+        app
+    }
+
+    /// Runs `line` and returns the last line it logged.
+    fn run(app: &mut App, line: &str) -> String {
+        app.world_mut().trigger(RunAdminCommand {
+            line: line.to_string(),
+            source: CommandSource::Console,
+        });
+        let log = app.world().resource::<ConsoleLog>();
+        log.lines.back().cloned().unwrap_or_default()
+    }
+
+    #[test]
+    fn offline_players_are_only_banned_by_id() {
+        let mut app = hosting_app();
+
+        assert_eq!(
+            run(&mut app, "ban Mallory"),
+            "No player named \"Mallory\" is online; ban by id"
+        );
+        assert!(app.world().resource::<AdmissionPolicy>().banned.is_empty());
+
+        let id = Uuid::new_v4();
+        let typed = id.to_string().to_uppercase();
+        assert_eq!(
+            run(&mut app, &format!("ban {typed}")),
+            format!("Banned player id {id}")
+        );
+        app.world_mut().flush();
+        assert!(app
+            .world()
+            .resource::<AdmissionPolicy>()
+            .banned
+            .contains(&id.to_string()));
+
+        run(&mut app, &format!("unban {id}"));
+    }
+
+    #[test]
+    fn players_sharing_a_name_are_picked_by_id() {
+        let mut app = hosting_app();
+        let ids = [Uuid::new_v4(), Uuid::new_v4()];
+        for id in ids {
+            app.world_mut().spawn(PlayerIdentity {
+                id,
+                name: "Eve".to_string(),
+            });
+        }
+
+        let ambiguous = format!(
+            "2 players are named \"eve\", use one of their ids: {}, {}",
+            ids[0], ids[1]
+        );
+        assert_eq!(run(&mut app, "kick eve"), ambiguous);
+        assert_eq!(run(&mut app, "ban eve"), ambiguous);
+        app.world_mut().flush();
+        assert!(app.world().resource::<AdmissionPolicy>().banned.is_empty());
+
+        assert_eq!(
+            run(&mut app, &format!("ban {}", ids[1])),
+            format!("Banned Eve ({})", ids[1])
+        );
+        app.world_mut().flush();
+        assert_eq!(
+            app.world().resource::<AdmissionPolicy>().banned,
+            [ids[1].to_string()].into()
+        );
+
+        run(&mut app, &format!("unban {}", ids[1]));
+    }
+
+    #[test]
+    fn console_lists_players_and_stops_the_session() {
+        let mut app = hosting_app();
+
+        app.world_mut().trigger(RunAdminCommand {
+            line: "list".to_string(),
+            source: CommandSource::Console,
+        });
+        let host_name = app.world().resource::<PlayerProfile>().name.clone();
+        let log = app.world().resource::<ConsoleLog>();
+        assert!(log.lines.iter().any(|line| line == "1 players online"));
+        assert!(log
+            .lines
+            .iter()
+            .any(|line| line.contains(&format!("{host_name} (host)"))));
+
+        app.world_mut().trigger(RunAdminCommand {
+            line: "stop".to_string(),
+            source: CommandSource::Console,
+        });
+        for _ in 0..10 {
+            app.update();
+        }
+        assert_eq!(
+            *app.world().resource::<State<SessionType>>().get(),
+            SessionType::None
+        );
+    }
+}
@@ -0,0 +1,276 @@
+use {
+    crate::protocol::{ClientChat, ServerChat},
+    aeronet::io::connection::Disconnect,
+    bevy::{ecs::system::SystemParam, platform::collections::HashMap, prelude::*},
+    bevy_replicon::prelude::*,
+    core::{any::TypeId, marker::PhantomData, time::Duration},
+};
+
+/// Five messages at once, then one per second.
+pub const CHAT_RATE_LIMIT: RateLimit = RateLimit {
+    burst: 5,
+    per_second: 1.0,
+};
+
+/// Dropped messages a client gets away with before it is disconnected, forgiven one per second.
+const FLOOD_TOLERANCE: RateLimit = RateLimit {
+    burst: 20,
+    per_second: 1.0,
+};
+
+const FLOOD_DISCONNECT_REASON: &str = "Disconnected for flooding the server";
+
+/// Gives every connected client a [`ClientRateLimiter`] and sets the limits of the
+/// client messages from [`crate::protocol::ProtocolPlugin`].
+pub struct RateLimitPlugin;
+
+impl Plugin for RateLimitPlugin {
+    fn build(&self, app: &mut App) {
+        app.limit_client_message::<ClientChat>(CHAT_RATE_LIMIT)
+            .add_observer(on_client_connected_add_rate_limiter);
+    }
+}
+
+pub trait RateLimitAppExt {
+    /// Required for reading `M` through [`RateLimitedMessages`].
+    fn limit_client_message<M: Message>(&mut self, limit: RateLimit) -> &mut Self;
+}
+
+impl RateLimitAppExt for App {
+    fn limit_client_message<M: Message>(&mut self, limit: RateLimit) -> &mut Self {
+        self.insert_resource(ClientMessageLimit::<M> {
+            limit,
+            marker: PhantomData,
+        })
+    }
+}
+
+/// Token bucket settings: `burst` messages at once, refilled at `per_second`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub burst: u32,
+    pub per_second: f32,
+}
+
+#[derive(Resource)]
+pub struct ClientMessageLimit<M> {
+    pub limit: RateLimit,
+    marker: PhantomData<M>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TokenBucket {
+    tokens: f32,
+    refilled_at: Duration,
+}
+
+impl TokenBucket {
+    fn full(limit: RateLimit) -> Self {
+        Self {
+            tokens: limit.burst as f32,
+            refilled_at: Duration::ZERO,
+        }
+    }
+
+    fn try_take(&mut self, limit: RateLimit, now: Duration) -> bool {
+        let elapsed = now.saturating_sub(self.refilled_at).as_secs_f32();
+        self.tokens = (self.tokens + elapsed * limit.per_second).min(limit.burst as f32);
+        self.refilled_at = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// What to do with a message from a client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Verdict {
+    Allow,
+    Drop,
+    /// Drop it and tell the client to slow down, sent once per burst of dropped messages.
+    Warn,
+    Disconnect,
+}
+
+/// Per message type buckets of one connected client.
+#[derive(Component, Debug)]
+pub struct ClientRateLimiter {
+    buckets: HashMap<TypeId, TokenBucket>,
+    tolerance: TokenBucket,
+    warned: bool,
+    disconnecting: bool,
+}
+
+impl Default for ClientRateLimiter {
+    fn default() -> Self {
+        Self {
+            buckets: HashMap::default(),
+            tolerance: TokenBucket::full(FLOOD_TOLERANCE),
+            warned: false,
+            disconnecting: false,
+        }
+    }
+}
+
+impl ClientRateLimiter {
+    fn check<M: 'static>(&mut self, limit: RateLimit, now: Duration) -> Verdict {
+        if self.disconnecting {
+            return Verdict::Drop;
+        }
+        let bucket = self
+            .buckets
+            .entry(TypeId::of::<M>())
+            .or_insert_with(|| TokenBucket::full(limit));
+        if bucket.try_take(limit, now) {
+            self.warned = false;
+            return Verdict::Allow;
+        }
+        if !self.tolerance.try_take(FLOOD_TOLERANCE, now) {
+            self.disconnecting = true;
+            return Verdict::Disconnect;
+        }
+        if std::mem::replace(&mut self.warned, true) {
+            Verdict::Drop
+        } else {
+            Verdict::Warn
+        }
+    }
+}
+
+/// Reads [`FromClient<M>`] like a [`MessageReader`], but drops what exceeds the client's
+/// [`ClientMessageLimit<M>`]. The host's own messages are never limited.
+#[derive(SystemParam)]
+pub struct RateLimitedMessages<'w, 's, M: Message> {
+    reader: MessageReader<'w, 's, FromClient<M>>,
+    limit: Res<'w, ClientMessageLimit<M>>,
+    limiters: Query<'w, 's, &'static mut ClientRateLimiter>,
+    time: Res<'w, Time<Real>>,
+    commands: Commands<'w, 's>,
+}
+
+impl<'w, 's, M: Message> RateLimitedMessages<'w, 's, M> {
+    pub fn read(&mut self) -> impl Iterator<Item = &FromClient<M>> + use<'_, 'w, 's, M> {
+        let now = self.time.elapsed();
+        let limit = self.limit.limit;
+        let limiters = &mut self.limiters;
+        let commands = &mut self.commands;
+        self.reader.read().filter(move |message| {
+            let ClientId::Client(client) = message.client_id else {
+                return true;
+            };
+            let Ok(mut limiter) = limiters.get_mut(client) else {
+                return true;
+            };
+            match limiter.check::<M>(limit, now) {
+                Verdict::Allow => true,
+                Verdict::Drop => false,
+                Verdict::Warn => {
+                    debug!("Rate limiting {} from {client}", ShortName::of::<M>());
+                    commands.write_message(ToClients {
+                        mode: SendMode::Direct(message.client_id),
+                        message: ServerChat {
+                            sender: "Server".to_string(),
+                            text: "You are sending messages too fast, slow down".to_string(),
+                        },
+                    });
+                    false
+                }
+                Verdict::Disconnect => {
+                    warn!("Disconnecting {client} for flooding the server");
+                    commands.trigger(Disconnect::new(client, FLOOD_DISCONNECT_REASON));
+                    false
+                }
+            }
+        })
+    }
+}
+
+fn on_client_connected_add_rate_limiter(trigger: On<Add, ConnectedClient>, mut commands: Commands) {
+    commands
+        .entity(trigger.event_target())
+        .insert(ClientRateLimiter::default());
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::player::PlayerProfile, crate::server::handle_client_chat};
+
+    #[derive(Resource, Default)]
+    struct Disconnected(Vec<Entity>);
+
+    #[test]
+    fn token_bucket_refills_over_time() {
+        let limit = RateLimit {
+            burst: 2,
+            per_second: 1.0,
+        };
+        let mut bucket = TokenBucket::full(limit);
+        assert!(bucket.try_take(limit, Duration::ZERO));
+        assert!(bucket.try_take(limit, Duration::ZERO));
+        assert!(!bucket.try_take(limit, Duration::from_millis(500)));
+        assert!(bucket.try_take(limit, Duration::from_millis(1000)));
+        // Never more than the burst, however long the client was quiet.
+        assert!(bucket.try_take(limit, Duration::from_secs(60)));
+        assert!(bucket.try_take(limit, Duration::from_secs(60)));
+        assert!(!bucket.try_take(limit, Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn flooding_client_is_warned_then_disconnected() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_message::<FromClient<ClientChat>>()
+            .add_message::<ToClients<ServerChat>>()
+            .init_resource::<PlayerProfile>()
+            .init_resource::<Disconnected>()
+            .limit_client_message::<ClientChat>(CHAT_RATE_LIMIT)
+            .add_observer(
+                |trigger: On<Disconnect>, mut disconnected: ResMut<Disconnected>| {
+                    disconnected.0.push(trigger.event_target());
+                },
+            )
+            .add_systems(Update, handle_client_chat);
+        let client = app.world_mut().spawn(ClientRateLimiter::default()).id();
+
+        let send = |app: &mut App, client_id: ClientId, count: usize| {
+            for _ in 0..count {
+                app.world_mut().write_message(FromClient {
+                    client_id,
+                    message: ClientChat {
+                        text: "spam".to_string(),
+                    },
+                });
+            }
+            app.update();
+            app.world_mut()
+                .resource_mut::<Messages<ToClients<ServerChat>>>()
+                .drain()
+                .map(|message| message.mode)
+                .collect::<Vec<_>>()
+        };
+
+        let modes = send(&mut app, ClientId::Client(client), 10);
+        let broadcasts = modes
+            .iter()
+            .filter(|mode| matches!(mode, SendMode::Broadcast))
+            .count();
+        assert_eq!(broadcasts, CHAT_RATE_LIMIT.burst as usize);
+        assert_eq!(
+            modes
+                .iter()
+                .filter(|mode| matches!(mode, SendMode::Direct(_)))
+                .count(),
+            1
+        );
+        assert!(app.world().resource::<Disconnected>().0.is_empty());
+
+        // The host is never limited.
+        assert_eq!(send(&mut app, ClientId::Server, 10).len(), 10);
+
+        send(&mut app, ClientId::Client(client), 30);
+        assert_eq!(app.world().resource::<Disconnected>().0, [client]);
+    }
+}
@@ -0,0 +1,595 @@
+use {
+    super::{
+        admission::passwords_match,
+        console::{AdminCommandCompleted, CommandSource, RunAdminCommand},
+    },
+    crate::status_management::SessionType,
+    bevy::prelude::*,
+    std::{
+        collections::HashMap,
+        io::{self, ErrorKind, Read, Write},
+        net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream},
+        time::{Duration, Instant},
+    },
+};
+
+pub const RCON_PORT: u16 = 25575;
+
+/// Wrong passwords an address may send before it gets locked out.
+const MAX_AUTH_ATTEMPTS: u32 = 3;
+/// The first lockout, doubling with every wrong password after it.
+const AUTH_LOCKOUT: Duration = Duration::from_secs(30);
+/// Longest lockout, an address that stays quiet this long starts over.
+const MAX_AUTH_LOCKOUT: Duration = Duration::from_secs(60 * 60);
+/// Longest request line, anything longer is a misbehaving client.
+const MAX_LINE_LENGTH: usize = 4096;
+/// Connections that have not authenticated yet, further ones are turned away.
+const MAX_PENDING_CONNECTIONS: usize = 8;
+/// How long a connection may take to authenticate before it gets dropped.
+const AUTH_TIMEOUT: Duration = Duration::from_secs(5);
+/// Unsent replies a connection may pile up, a client this far behind is not reading.
+const MAX_OUTGOING_LENGTH: usize = 1 << 20;
+
+/// Remote admin access over a line-based TCP protocol.
+///
+/// A client first sends `auth <password>`, then any console command, one per line. Every
+/// line is answered with either `ok <n>` followed by `n` output lines, or `error <message>`.
+pub struct RconPlugin;
+
+impl Plugin for RconPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RconConfig>()
+            .add_systems(OnEnter(SessionType::Singleplayer), start_rcon)
+            .add_systems(OnExit(SessionType::Singleplayer), stop_rcon)
+            .add_systems(
+                Update,
+                rcon_server_system.run_if(resource_exists::<RconServer>),
+            )
+            .add_observer(on_admin_command_completed);
+    }
+}
+
+/// Off unless enabled, and even then it refuses to start without a password.
+#[derive(Resource, Debug, Clone)]
+pub struct RconConfig {
+    pub enabled: bool,
+    /// Loopback by default, binding anything else exposes the admin commands to the network.
+    pub address: SocketAddr,
+    pub password: Option<String>,
+}
+
+impl Default for RconConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: SocketAddr::from((Ipv4Addr::LOCALHOST, RCON_PORT)),
+            password: None,
+        }
+    }
+}
+
+/// The listening endpoint while a hosted session runs.
+#[derive(Resource)]
+pub struct RconServer {
+    listener: TcpListener,
+    password: String,
+    connections: Vec<RconConnection>,
+    failures: AuthFailureLog,
+    next_id: u64,
+}
+
+impl RconServer {
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+}
+
+/// Wrong passwords from one address.
+#[derive(Debug)]
+struct AuthFailures {
+    count: u32,
+    last: Instant,
+}
+
+impl AuthFailures {
+    fn lockout(&self) -> Duration {
+        match self.count.checked_sub(MAX_AUTH_ATTEMPTS) {
+            None => Duration::ZERO,
+            Some(doublings) => AUTH_LOCKOUT
+                .saturating_mul(1 << doublings.min(16))
+                .min(MAX_AUTH_LOCKOUT),
+        }
+    }
+
+    fn is_locked_out(&self) -> bool {
+        self.last.elapsed() < self.lockout()
+    }
+}
+
+/// Wrong passwords per address, so reconnecting does not buy another round of guesses.
+#[derive(Debug, Default)]
+struct AuthFailureLog(HashMap<IpAddr, AuthFailures>);
+
+impl AuthFailureLog {
+    fn is_locked_out(&self, ip: IpAddr) -> bool {
+        self.0.get(&ip).is_some_and(AuthFailures::is_locked_out)
+    }
+
+    fn record(&mut self, ip: IpAddr) {
+        let failures = self.0.entry(ip).or_insert(AuthFailures {
+            count: 0,
+            last: Instant::now(),
+        });
+        failures.count += 1;
+        failures.last = Instant::now();
+    }
+
+    fn forget(&mut self, ip: IpAddr) {
+        self.0.remove(&ip);
+    }
+
+    /// Drops addresses that stayed quiet for the longest lockout.
+    fn expire(&mut self) {
+        self.0
+            .retain(|_, failures| failures.last.elapsed() < MAX_AUTH_LOCKOUT);
+    }
+}
+
+struct RconConnection {
+    id: u64,
+    stream: TcpStream,
+    peer: SocketAddr,
+    buffer: Vec<u8>,
+    /// Replies not yet accepted by the socket, written out by [`RconConnection::flush`].
+    outgoing: Vec<u8>,
+    connected_at: Instant,
+    authenticated: bool,
+    closed: bool,
+}
+
+impl RconConnection {
+    fn send(&mut self, reply: &str) {
+        self.outgoing.extend_from_slice(reply.as_bytes());
+        if self.outgoing.len() > MAX_OUTGOING_LENGTH {
+            self.closed = true;
+        }
+    }
+
+    /// Writes as much of the queued replies as the socket takes without blocking.
+    fn flush(&mut self) {
+        while !self.outgoing.is_empty() {
+            match self.stream.write(&self.outgoing) {
+                Ok(0) => {
+                    self.closed = true;
+                    break;
+                }
+                Ok(len) => {
+                    self.outgoing.drain(..len);
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(_) => {
+                    self.closed = true;
+                    break;
+                }
+            }
+        }
+    }
+
+    fn reply(&mut self, outcome: &Result<Vec<String>, String>) {
+        let mut reply = match outcome {
+            Ok(output) => format!("ok {}\n", output.len()),
+            // Keep the error to a single line, the protocol depends on it.
+            Err(err) => format!("error {}\n", err.replace('\n', " ")),
+        };
+        if let Ok(output) = outcome {
+            for line in output {
+                reply.push_str(&line.replace('\n', " "));
+                reply.push('\n');
+            }
+        }
+        self.send(&reply);
+    }
+
+    /// Complete lines received so far.
+    fn read_lines(&mut self) -> Vec<String> {
+        let mut chunk = [0u8; 1024];
+        loop {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => {
+                    self.closed = true;
+                    break;
+                }
+                Ok(len) => self.buffer.extend_from_slice(&chunk[..len]),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(_) => {
+                    self.closed = true;
+                    break;
+                }
+            }
+        }
+
+        let mut lines = Vec::new();
+        while let Some(end) = self.buffer.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            lines.push(String::from_utf8_lossy(&line).trim().to_string());
+        }
+        if self.buffer.len() > MAX_LINE_LENGTH {
+            self.send("error Line too long\n");
+            self.closed = true;
+        }
+        lines
+    }
+}
+
+fn start_rcon(mut commands: Commands, config: Res<RconConfig>) {
+    if !config.enabled {
+        return;
+    }
+    let Some(password) = config
+        .password
+        .clone()
+        .filter(|password| !password.is_empty())
+    else {
+        error!("RCON is enabled but has no password, not starting it");
+        return;
+    };
+
+    match bind_listener(config.address) {
+        Ok(listener) => {
+            info!("RCON listening on {}", config.address);
+            if !config.address.ip().is_loopback() {
+                warn!(
+                    "RCON is reachable from other machines on {}",
+                    config.address
+                );
+            }
+            commands.insert_resource(RconServer {
+                listener,
+                password,
+                connections: Vec::new(),
+                failures: AuthFailureLog::default(),
+                next_id: 0,
+            });
+        }
+        Err(err) => error!("Could not start RCON on {}: {err}", config.address),
+    }
+}
+
+fn bind_listener(address: SocketAddr) -> io::Result<TcpListener> {
+    let listener = TcpListener::bind(address)?;
+    listener.set_nonblocking(true)?;
+    Ok(listener)
+}
+
+fn stop_rcon(mut commands: Commands) {
+    commands.remove_resource::<RconServer>();
+}
+
+fn rcon_server_system(mut commands: Commands, mut server: ResMut<RconServer>) {
+    let server = &mut *server;
+    server.failures.expire();
+    while let Ok((stream, peer)) = server.listener.accept() {
+        if server.failures.is_locked_out(peer.ip()) {
+            warn!("Refusing RCON connection from {peer}, too many wrong passwords");
+            continue;
+        }
+        let pending = server
+            .connections
+            .iter()
+            .filter(|connection| !connection.authenticated)
+            .count();
+        if pending >= MAX_PENDING_CONNECTIONS {
+            warn!("Too many unauthenticated RCON connections, refusing {peer}");
+            continue;
+        }
+        if stream.set_nonblocking(true).is_err() {
+            continue;
+        }
+        info!("RCON connection from {peer}");
+        server.next_id += 1;
+        server.connections.push(RconConnection {
+            id: server.next_id,
+            stream,
+            peer,
+            buffer: Vec::new(),
+            outgoing: Vec::new(),
+            connected_at: Instant::now(),
+            authenticated: false,
+            closed: false,
+        });
+    }
+
+    for connection in &mut server.connections {
+        for line in connection.read_lines() {
+            if line.is_empty() {
+                continue;
+            }
+            if connection.authenticated {
+                commands.trigger(RunAdminCommand {
+                    line,
+                    source: CommandSource::Rcon(connection.id),
+                });
+                continue;
+            }
+
+            let ip = connection.peer.ip();
+            match line.strip_prefix("auth ") {
+                // Another connection from the same address may have used up the guesses.
+                Some(_) if server.failures.is_locked_out(ip) => {
+                    connection.reply(&Err("Too many wrong passwords".to_string()));
+                    connection.closed = true;
+                }
+                Some(password) if passwords_match(password, &server.password) => {
+                    info!("RCON connection from {} authenticated", connection.peer);
+                    server.failures.forget(ip);
+                    connection.authenticated = true;
+                    connection.reply(&Ok(Vec::new()));
+                }
+                Some(_) => {
+                    warn!("Wrong RCON password from {}", connection.peer);
+                    server.failures.record(ip);
+                    connection.reply(&Err("Wrong password".to_string()));
+                    if server.failures.is_locked_out(ip) {
+                        connection.closed = true;
+                    }
+                }
+                None => {
+                    connection.reply(&Err("Authenticate with auth <password> first".to_string()))
+                }
+            }
+            if connection.closed {
+                break;
+            }
+        }
+        if !connection.authenticated && connection.connected_at.elapsed() >= AUTH_TIMEOUT {
+            warn!(
+                "RCON connection from {} did not authenticate in time",
+                connection.peer
+            );
+            connection.send("error Authentication timed out\n");
+            connection.closed = true;
+        }
+        // Replies to commands completed last frame are queued by then as well.
+        connection.flush();
+    }
+
+    server.connections.retain(|connection| {
+        if connection.closed {
+            info!("RCON connection from {} closed", connection.peer);
+        }
+        !connection.closed
+    });
+}
+
+fn on_admin_command_completed(
+    trigger: On<AdminCommandCompleted>,
+    server: Option<ResMut<RconServer>>,
+) {
+    let CommandSource::Rcon(id) = trigger.source else {
+        return;
+    };
+    let Some(mut server) = server else {
+        return;
+    };
+    if let Some(connection) = server
+        .connections
+        .iter_mut()
+        .find(|connection| connection.id == id)
+    {
+        connection.reply(&trigger.outcome);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        std::{io::BufRead, io::BufReader, time::Duration},
+    };
+
+    fn rcon_app(config: RconConfig) -> App {
+        let mut app = crate::test_app();
+        app.insert_resource(config);
+        app.update();
+        app.world_mut()
+            .resource_mut::<NextState<SessionType>>()
+            .set(SessionType::Singleplayer);
+        for _ in 0..3 {
+            app.update();
+        }
+        app
+    }
+
+    /// Sends `line` and reads one reply, updating the app until it arrives.
+    fn request(app: &mut App, reader: &mut BufReader<TcpStream>, line: &str) -> Vec<String> {
+        reader
+            .get_mut()
+            .write_all(format!("{line}\n").as_bytes())
+            .unwrap();
+        let mut read_line = |app: &mut App| {
+            let mut reply = String::new();
+            for _ in 0..100 {
+                app.update();
+                match reader.read_line(&mut reply) {
+                    Ok(_) if reply.ends_with('\n') => return reply.trim_end().to_string(),
+                    _ => {}
+                }
+            }
+            panic!("no reply to {line:?}");
+        };
+
+        let header = read_line(app);
+        let count = header
+            .strip_prefix("ok ")
+            .map_or(0, |count| count.parse().unwrap());
+        let mut reply = vec![header];
+        for _ in 0..count {
+            reply.push(read_line(app));
+        }
+        reply
+    }
+
+    #[test]
+    fn rcon_is_off_by_default() {
+        let app = rcon_app(RconConfig {
+            password: Some("secret".to_string()),
+            ..default()
+        });
+        assert!(!app.world().contains_resource::<RconServer>());
+    }
+
+    #[test]
+    fn loopback_client_authenticates_and_runs_commands() {
+        let mut app = rcon_app(RconConfig {
+            enabled: true,
+            address: SocketAddr::from((Ipv4Addr::LOCALHOST, 0)),
+            password: Some("secret".to_string()),
+        });
+        let address = app.world().resource::<RconServer>().local_addr().unwrap();
+        assert!(address.ip().is_loopback());
+
+        let stream = TcpStream::connect(address).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_millis(10)))
+            .unwrap();
+        let mut reader = BufReader::new(stream);
+
+        assert_eq!(
+            request(&mut app, &mut reader, "list"),
+            ["error Authenticate with auth <password> first"]
+        );
+        assert_eq!(
+            request(&mut app, &mut reader, "auth guess"),
+            ["error Wrong password"]
+        );
+        assert_eq!(request(&mut app, &mut reader, "auth secret"), ["ok 0"]);
+
+        let list = request(&mut app, &mut reader, "list");
+        assert_eq!(list[..2], ["ok 2", "1 players online"]);
+        assert!(list[2].contains("(host)"));
+        assert_eq!(
+            request(&mut app, &mut reader, "kick nobody"),
+            ["error No player named \"nobody\" is online"]
+        );
+
+        assert_eq!(
+            request(&mut app, &mut reader, "stop"),
+            ["ok 1", "Stopping the server"]
+        );
+        for _ in 0..10 {
+            app.update();
+        }
+        assert!(!app.world().contains_resource::<RconServer>());
+    }
+
+    fn listening_app() -> (App, SocketAddr) {
+        let app = rcon_app(RconConfig {
+            enabled: true,
+            address: SocketAddr::from((Ipv4Addr::LOCALHOST, 0)),
+            password: Some("secret".to_string()),
+        });
+        let address = app.world().resource::<RconServer>().local_addr().unwrap();
+        (app, address)
+    }
+
+    /// Whether the server hung up on `stream`, updating the app until it does.
+    fn hung_up(app: &mut App, stream: &mut TcpStream) -> bool {
+        stream
+            .set_read_timeout(Some(Duration::from_millis(10)))
+            .unwrap();
+        let mut chunk = [0u8; 256];
+        for _ in 0..100 {
+            app.update();
+            match stream.read(&mut chunk) {
+                Ok(0) => return true,
+                Err(err) if err.kind() == ErrorKind::ConnectionReset => return true,
+                _ => {}
+            }
+        }
+        false
+    }
+
+    #[test]
+    fn reconnecting_does_not_reset_the_wrong_password_count() {
+        let (mut app, address) = listening_app();
+        let connect = || {
+            let stream = TcpStream::connect(address).unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_millis(10)))
+                .unwrap();
+            BufReader::new(stream)
+        };
+
+        let mut first = connect();
+        for _ in 1..MAX_AUTH_ATTEMPTS {
+            assert_eq!(
+                request(&mut app, &mut first, "auth guess"),
+                ["error Wrong password"]
+            );
+        }
+        drop(first);
+
+        let mut second = connect();
+        assert_eq!(
+            request(&mut app, &mut second, "auth guess"),
+            ["error Wrong password"]
+        );
+        assert!(hung_up(&mut app, second.get_mut()));
+
+        // Locked out, even with the right password.
+        let mut third = connect();
+        third.get_mut().write_all(b"auth secret\n").unwrap();
+        assert!(hung_up(&mut app, third.get_mut()));
+        assert!(app.world().resource::<RconServer>().connections.is_empty());
+    }
+
+    #[test]
+    fn lockouts_double_up_to_the_limit() {
+        let lockouts: Vec<u64> = (0..=MAX_AUTH_ATTEMPTS + 8)
+            .map(|count| {
+                AuthFailures {
+                    count,
+                    last: Instant::now(),
+                }
+                .lockout()
+                .as_secs()
+            })
+            .collect();
+        assert_eq!(
+            lockouts,
+            [0, 0, 0, 30, 60, 120, 240, 480, 960, 1920, 3600, 3600]
+        );
+    }
+
+    #[test]
+    fn unauthenticated_connections_are_capped() {
+        let (mut app, address) = listening_app();
+        let mut pending: Vec<_> = (0..MAX_PENDING_CONNECTIONS)
+            .map(|_| TcpStream::connect(address).unwrap())
+            .collect();
+        app.update();
+        assert_eq!(
+            app.world().resource::<RconServer>().connections.len(),
+            MAX_PENDING_CONNECTIONS
+        );
+
+        let mut refused = TcpStream::connect(address).unwrap();
+        assert!(hung_up(&mut app, &mut refused));
+        assert!(!hung_up(&mut app, &mut pending[0]));
+    }
+
+    #[test]
+    fn connections_that_do_not_authenticate_in_time_are_dropped() {
+        let (mut app, address) = listening_app();
+        let mut idle = TcpStream::connect(address).unwrap();
+        app.update();
+        for connection in &mut app.world_mut().resource_mut::<RconServer>().connections {
+            connection.connected_at -= AUTH_TIMEOUT;
+        }
+
+        assert!(hung_up(&mut app, &mut idle));
+        assert!(app.world().resource::<RconServer>().connections.is_empty());
+    }
+}
@@ -1,6 +1,9 @@
 use {
     crate::{
+        dedicated::DedicatedServer,
         local::*,
+        player::{PlayerIdentity, PlayerProfile},
+        server::{shutdown_countdown_over, NotifyServerShutdown, PendingShutdown},
         status_management::{
             SessionType, SetSingleplayerShutdownStep, SetSingleplayerStatus,
             SingleplayerShutdownStep, SingleplayerStatus,
@@ -27,28 +30,39 @@ impl Plugin for SingleplayerLogicPlugin {
         ))
         .add_systems(
             OnEnter(SingleplayerStatus::Starting),
-            on_singleplayer_starting,
+            on_singleplayer_starting.run_if(not(resource_exists::<DedicatedServer>)),
         )
         .add_observer(on_singleplayer_ready)
         .add_systems(
             OnEnter(SingleplayerStatus::Running),
             on_singleplayer_running,
         )
+        .add_systems(
+            OnEnter(SingleplayerShutdownStep::DisconnectRemoteClients),
+            on_singleplayer_disconnect_remote_clients,
+        )
         .add_systems(
             Update,
-            singleplayer_stopping.run_if(in_state(SingleplayerStatus::Stopping)),
+            // Remote players get the whole countdown before the first step disconnects them.
+            singleplayer_stopping
+                .run_if(in_state(SingleplayerStatus::Stopping).and(shutdown_countdown_over)),
         );
     }
 }
 
-pub fn on_singleplayer_starting(mut commands: Commands) {
+pub fn on_singleplayer_starting(mut commands: Commands, profile: Res<PlayerProfile>) {
     info!("Starting Singleplayer");
 
     let server_entity = commands
         .spawn((Name::new("Local Server"), LocalSession, LocalServer))
         .id();
     let client_entity = commands
-        .spawn((Name::new("Local Client"), LocalSession, LocalClient))
+        .spawn((
+            Name::new("Local Client"),
+            LocalSession,
+            LocalClient,
+            PlayerIdentity::from(&*profile),
+        ))
         .id();
 
     commands.queue(ChannelIo::open(server_entity, client_entity));
@@ -71,6 +85,12 @@ pub fn on_singleplayer_running(mut _commands: Commands) {
     debug!("Singleplayer is running");
 }
 
+pub fn on_singleplayer_disconnect_remote_clients(mut commands: Commands) {
+    commands.trigger(NotifyServerShutdown {
+        reason: "Singleplayer closing".to_string(),
+    });
+}
+
 pub fn singleplayer_stopping(
     mut commands: Commands,
     step: Res<State<SingleplayerShutdownStep>>,
@@ -82,11 +102,12 @@ pub fn singleplayer_stopping(
 ) {
     match step.get() {
         SingleplayerShutdownStep::DisconnectRemoteClients => {
-            // 1. Tick: Remote-Clients trennen (public / LAN)
+            // 1. Tick: Remote-Clients trennen (public / LAN), sobald der Countdown abgelaufen ist
             for client in &client_query {
                 commands.trigger(Disconnect::new(client, "Singleplayer closing"));
             }
             if client_query.is_empty() {
+                commands.remove_resource::<PendingShutdown>();
                 commands.trigger(SetSingleplayerShutdownStep::Next);
             }
         }
@@ -1,10 +1,8 @@
+#[cfg(feature = "client")]
+use crate::{client::ClientTarget, notifications::Notify, status_management::ClientStatus};
 use {
     super::main::MainMenuContext,
-    crate::{
-        client::ClientTarget,
-        notifications::Notify,
-        status_management::{ClientStatus, ServerVisibility, SessionType, SingleplayerStatus},
-    },
+    crate::status_management::{ServerVisibility, SessionType, SingleplayerStatus},
     bevy::prelude::*,
 };
 
@@ -18,8 +16,10 @@ impl Plugin for MultiplayerMenuPlugin {
             .add_sub_state::<JoinGameMenuScreen>()
             .add_observer(handle_overview_nav)
             .add_observer(handle_host_new_game_nav)
-            .add_observer(handle_host_saved_game_nav)
-            .add_observer(handle_join_game_nav);
+            .add_observer(handle_host_saved_game_nav);
+        // Joining needs the client, a server-only build stays in the menu.
+        #[cfg(feature = "client")]
+        app.add_observer(handle_join_game_nav);
     }
 }
 
@@ -188,6 +188,7 @@ fn handle_host_saved_game_nav(
     }
 }
 
+#[cfg(feature = "client")]
 fn handle_join_game_nav(
     trigger: On<SetJoinGame>,
     current_setup: Res<State<MultiplayerSetup>>,
@@ -105,9 +105,10 @@ impl ComputedStates for SessionLifecycle {
                 // Client logic
                 match status {
                     ClientStatus::Running => Some(SessionLifecycle::Active),
-                    ClientStatus::Connecting | ClientStatus::Connected | ClientStatus::Syncing => {
-                        Some(SessionLifecycle::Loading)
-                    }
+                    ClientStatus::Connecting
+                    | ClientStatus::Connected
+                    | ClientStatus::Syncing
+                    | ClientStatus::Reconnecting => Some(SessionLifecycle::Loading),
                     ClientStatus::Disconnecting => Some(SessionLifecycle::Cleanup),
                 }
             }),
@@ -85,6 +85,8 @@ pub enum ClientStatus {
     Connected,
     Syncing,
     Running,
+    /// The connection was lost while playing, retrying with the same target.
+    Reconnecting,
     Disconnecting,
 }
 
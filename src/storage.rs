@@ -0,0 +1,60 @@
+use std::{path::PathBuf, sync::OnceLock};
+
+/// Name of the per-user folder that holds everything we persist between sessions.
+const APP_DIR_NAME: &str = "fos_server";
+
+/// Set once at startup by [`set_data_dir`].
+static DATA_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Makes [`data_dir`] return `dir` for the rest of the process, e.g. from a command line flag.
+///
+/// Call it before the app is built, later reads would already have used the default. Only the
+/// first call takes effect, the rejected `dir` is handed back otherwise.
+pub fn set_data_dir(dir: PathBuf) -> Result<(), PathBuf> {
+    DATA_DIR.set(dir)
+}
+
+/// Per-user directory for persisted state such as the server identity.
+///
+/// [`set_data_dir`] wins, then `FOS_DATA_DIR`, then the platform default. Either override runs
+/// several hosts on one machine.
+pub fn data_dir() -> PathBuf {
+    // Unit tests must never touch the real user profile.
+    if cfg!(test) {
+        return std::env::temp_dir().join("fos_server_test");
+    }
+
+    if let Some(dir) = DATA_DIR.get() {
+        return dir.clone();
+    }
+    if let Some(dir) = std::env::var_os("FOS_DATA_DIR") {
+        return PathBuf::from(dir);
+    }
+
+    platform_data_dir().join(APP_DIR_NAME)
+}
+
+#[cfg(target_os = "windows")]
+fn platform_data_dir() -> PathBuf {
+    std::env::var_os("APPDATA")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+#[cfg(target_os = "macos")]
+fn platform_data_dir() -> PathBuf {
+    std::env::var_os("HOME")
+        .map(|home| PathBuf::from(home).join("Library/Application Support"))
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn platform_data_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("XDG_DATA_HOME") {
+        return PathBuf::from(dir);
+    }
+
+    std::env::var_os("HOME")
+        .map(|home| PathBuf::from(home).join(".local/share"))
+        .unwrap_or_else(|| PathBuf::from("."))
+}